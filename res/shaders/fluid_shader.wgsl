@@ -3,27 +3,64 @@ struct Particle {
     @location(1) velocity: vec2<f32>,
     @location(2) mass: f32,
     @location(3) density: f32,
+    @location(4) alive: u32,
+    @location(5) group: u32,
+    @location(6) acceleration: vec2<f32>,
 }
 
 struct Data {
     @location(0) time_step: f32,
     @location(1) width: u32,
+    @location(2) bounds_min: vec2<f32>,
+    @location(3) bounds_max: vec2<f32>,
+    @location(4) boundaries: array<u32, 4>, // left, right, bottom, top
+    @location(5) density_epsilon: f32,
+    @location(6) wall_velocities: array<f32, 4>, // left, right, bottom, top
+    @location(7) surface_tension: f32,
+    @location(8) cohesion_balance: f32, // 0 only curvature, 1 only cohesion
+    @location(9) max_cohesion: f32,
+    @location(10) support_radius: f32,
+    @location(11) integrator: u32,
+    @location(12) gravity: vec2<f32>,
+    @location(13) last_time_step: f32,
+    @location(14) damping: f32,
+    @location(15) viscosity: f32,
 }
 
 @group(0) @binding(0) var<storage, read_write> ins:  array<Particle>;
 @group(0) @binding(1) var<storage, read_write> outs: array<Particle>;
 @group(0) @binding(2) var<storage> info: Data;
 @group(0) @binding(3) var<storage, read_write> surface: array<f32>;
+@group(0) @binding(4) var<storage, read_write> divergence: array<f32>;
 
 
-let H = 6f;
+// kernel support radius, set from info at the start of main
+var<private> H: f32;
 let PI = 3.1415926535f;
 let gas_constant = 0.08f;
 let surface_treshold = 0.3f;
-let tension_coeficient = 0.0f;
-let viscous_coeficient = 0.7f;
 let rest_density = 10f;
 
+let EPSILON = 0.000001f;
+
+let REFLECT  = 0u;
+let OPEN     = 1u;
+let PERIODIC = 2u;
+
+let SEMI_IMPLICIT_EULER = 0u;
+let LEAPFROG            = 1u;
+
+
+// normalize the vector, zero vector for (nearly) zero input instead of NaN
+fn safe_normalize(v: vec2<f32>) -> vec2<f32> {
+    let len = length(v);
+
+    if (len < EPSILON) {
+        return vec2(0f);
+    }
+
+    return v / len;
+}
 
 fn poly6_kernel(ri: vec2<f32>, rj: vec2<f32>) -> f32 {
     let r = distance(ri, rj);
@@ -35,6 +72,11 @@ fn poly6_kernel(ri: vec2<f32>, rj: vec2<f32>) -> f32 {
     return 315f / (64f * PI * pow(H, 9f)) * pow(pow(H, 2f) - pow(r, 2f), 3f);
 }
 
+// poly6 kernel at zero distance, the self contribution the kernel itself leaves out
+fn poly6_self_kernel() -> f32 {
+    return 315f / (64f * PI * pow(H, 9f)) * pow(H, 6f);
+}
+
 fn grad_poly6_kernel(ri: vec2<f32>, rj: vec2<f32>) -> f32 {
     let r = distance(ri, rj);
 
@@ -97,6 +139,50 @@ fn lap_viscosity_kernel(ri: vec2<f32>, rj: vec2<f32>) -> f32 {
 
 
 
+// get the image of the neighbor closest to the particle, taking periodic faces into account
+fn periodic_image(ri: vec2<f32>, rj: vec2<f32>) -> vec2<f32> {
+    let extent = info.bounds_max - info.bounds_min;
+    var image = rj;
+
+    if (info.boundaries[0] == PERIODIC) {
+        let dx = rj.x - ri.x;
+
+        if (dx > extent.x * 0.5f) {
+            image.x -= extent.x;
+        } else if (dx < -extent.x * 0.5f) {
+            image.x += extent.x;
+        }
+    }
+    if (info.boundaries[2] == PERIODIC) {
+        let dy = rj.y - ri.y;
+
+        if (dy > extent.y * 0.5f) {
+            image.y -= extent.y;
+        } else if (dy < -extent.y * 0.5f) {
+            image.y += extent.y;
+        }
+    }
+
+    return image;
+}
+
+// reflect the velocity relative to the moving wall, so the wall pushes the particle
+fn reflect_wall(velocity: vec2<f32>, normal: vec2<f32>, wall_velocity: vec2<f32>) -> vec2<f32> {
+    var relative = velocity - wall_velocity;
+
+    if (dot(relative, normal) < 0f) {
+        relative = reflect(relative, normal);
+    }
+
+    return relative + wall_velocity;
+}
+
+// contribution of the neighbor to the velocity divergence, negative when approaching
+fn calc_divergence(mj: f32, vi: vec2<f32>, vj: vec2<f32>, ri: vec2<f32>, rj: vec2<f32>) -> f32 {
+    let gradient = grad_spiky_kernel(ri, rj) * safe_normalize(ri - rj);
+    return mj * dot(vj - vi, gradient);
+}
+
 fn calc_density(mj: f32, ri: vec2<f32>, rj: vec2<f32>) -> f32 {
     return mj * poly6_kernel(ri, rj);
 }
@@ -107,9 +193,9 @@ fn calc_particle_pressure(k: f32, ro: f32, rest_ro: f32) -> f32 {
 }
 
 fn calc_pressure(mj: f32, pi: f32, pj: f32, roj: f32, ri: vec2<f32>, rj: vec2<f32>) -> vec2<f32> {
-    let r = normalize(rj - ri);
+    let r = safe_normalize(rj - ri);
 
-    if (roj == 0f) {
+    if (roj < info.density_epsilon) {
         return vec2(0f);
     }
 
@@ -119,7 +205,7 @@ fn calc_pressure(mj: f32, pi: f32, pj: f32, roj: f32, ri: vec2<f32>, rj: vec2<f3
 
 // don't forget to multiply by mu
 fn calc_viscosity(mj: f32, vi: vec2<f32>, vj: vec2<f32>, roj: f32, ri: vec2<f32>, rj: vec2<f32>) -> vec2<f32> {
-    if (roj == 0f) {
+    if (roj < info.density_epsilon) {
         return vec2(0f);
     }
 
@@ -127,7 +213,7 @@ fn calc_viscosity(mj: f32, vi: vec2<f32>, vj: vec2<f32>, roj: f32, ri: vec2<f32>
 }
 
 fn calc_color_field(mj: f32, roj: f32, smoothed: f32, r: vec2<f32>) -> vec2<f32> {
-    if (roj == 0f) {
+    if (roj < info.density_epsilon) {
         return vec2(0f);
     }
 
@@ -135,12 +221,12 @@ fn calc_color_field(mj: f32, roj: f32, smoothed: f32, r: vec2<f32>) -> vec2<f32>
 }
 
 fn grad_color_field(mj: f32, roj: f32, ri: vec2<f32>, rj: vec2<f32>) -> vec2<f32> {
-    let r = normalize(rj - ri);
+    let r = safe_normalize(rj - ri);
     return calc_color_field(mj, roj, grad_poly6_kernel(ri, rj), r);
 }
 
 fn lap_color_field(mj: f32, roj: f32, ri: vec2<f32>, rj: vec2<f32>) -> vec2<f32> {
-    let r = normalize(rj - ri);
+    let r = safe_normalize(rj - ri);
     return calc_color_field(mj, roj, lap_poly6_kernel(ri, rj), r); 
 }
 
@@ -152,79 +238,157 @@ fn calc_tension(grad: vec2<f32>, lap: vec2<f32>) -> vec2<f32> {
         return vec2(0f);
     }
 
-    let force = - tension_coeficient * lap * normalize(grad);
+    let force = - info.surface_tension * lap * safe_normalize(grad);
+    return force;
+}
+
+// cohesion kernel of Akinci et al. 2013, repulsive for r < H / 2
+fn cohesion_kernel(ri: vec2<f32>, rj: vec2<f32>) -> f32 {
+    let r = distance(ri, rj);
+
+    if (H < r || r <= 0f) {
+        return 0f;
+    }
+
+    let coeficient = 32f / (PI * pow(H, 9f));
+
+    if (2f * r > H) {
+        return coeficient * pow(H - r, 3f) * pow(r, 3f);
+    }
+
+    return coeficient * (2f * pow(H - r, 3f) * pow(r, 3f) - pow(H, 6f) / 64f);
+}
+
+// cohesion force clamped, so the particles can't collapse into tight clusters
+fn calc_cohesion(mi: f32, mj: f32, ri: vec2<f32>, rj: vec2<f32>) -> vec2<f32> {
+    let force = - info.surface_tension * mi * mj * cohesion_kernel(ri, rj) * safe_normalize(ri - rj);
+    let magnitude = length(force);
+
+    if (magnitude > info.max_cohesion) {
+        return force * info.max_cohesion / magnitude;
+    }
+
+    return force;
 }
 
 @compute @workgroup_size(8, 8)
 fn main(
     @builtin(global_invocation_id) global_id: vec3<u32>,
 ) {
-    let id = global_id.y * info.width + global_id.x;
+    H = info.support_radius;
+
+    // invocations past the particles work on the last one, but never write it
+    let count = arrayLength(&ins);
+    let global_idx = global_id.y * info.width + global_id.x;
+    let is_valid = global_id.x < info.width && global_idx < count;
+    let id = min(global_idx, count - 1u);
     var particle = ins[id];
 
-    // calculate density
-    var density = 0f;
+    // calculate density, the particle is its own neighbor with m * W(0), 
+    // the loops skip it as the kernel and its gradients vanish at zero distance
+    var density = particle.mass * poly6_self_kernel();
     for (var j: i32 = 0; j < i32(arrayLength(&ins)); j++) {
         if (id == u32(j)) {
             continue;
         }
 
         let neighbor = ins[j];
-        density += calc_density(neighbor.mass, particle.position, neighbor.position);
+        if (neighbor.alive == 0u) {
+            continue;
+        }
+
+        let neighbor_position = periodic_image(particle.position, neighbor.position);
+        density += calc_density(neighbor.mass, particle.position, neighbor_position);
     }
     
     let pressure = calc_particle_pressure(gas_constant, particle.density, rest_density);
 
     particle.density = density;
-    ins[id] = particle;
+    if (is_valid) {
+        ins[id] = particle;
+    }
 
 // todo: test if working properly, in case move the density update into separate unit
     storageBarrier();
 
+    if (!is_valid) {
+        return;
+    }
+
+    // removed particles are only carried over
+    if (particle.alive == 0u) {
+        outs[id] = particle;
+        return;
+    }
+
     // calculate forces 
     var pressure_force = vec2(0f);
     var viscous_force  = vec2(0f);
     var tension_grad   = vec2(0f);
     var tension_lap    = vec2(0f);
+    var cohesion_force = vec2(0f);
+    var velocity_divergence = 0f;
 
     for (var j: i32 = 0; j < i32(arrayLength(&ins)); j++) {
+        // no self force
         if (id == u32(j)) {
             continue;
         }
 
         let neighbor = ins[j];
+        if (neighbor.alive == 0u) {
+            continue;
+        }
+
+        let neighbor_position = periodic_image(particle.position, neighbor.position);
         
         // pressure calculation
         let neighbor_pressure = calc_particle_pressure(gas_constant, neighbor.density, rest_density);
-        pressure_force += calc_pressure(neighbor.mass, pressure, neighbor_pressure, neighbor.density, particle.position, neighbor.position);
+        pressure_force += calc_pressure(neighbor.mass, pressure, neighbor_pressure, neighbor.density, particle.position, neighbor_position);
     
         // viscosity calculation
-        viscous_force += calc_viscosity(neighbor.mass, particle.velocity, neighbor.velocity, neighbor.density, particle.position, neighbor.position);
+        viscous_force += calc_viscosity(neighbor.mass, particle.velocity, neighbor.velocity, neighbor.density, particle.position, neighbor_position);
 
         // surface tension calculation 
-        tension_grad += grad_color_field(neighbor.mass, neighbor.density, particle.position, neighbor.position);
-        tension_lap  +=  lap_color_field(neighbor.mass, neighbor.density, particle.position, neighbor.position);
+        tension_grad += grad_color_field(neighbor.mass, neighbor.density, particle.position, neighbor_position);
+        tension_lap  +=  lap_color_field(neighbor.mass, neighbor.density, particle.position, neighbor_position);
+        cohesion_force += calc_cohesion(particle.mass, neighbor.mass, particle.position, neighbor_position);
+
+        // velocity divergence calculation
+        velocity_divergence += calc_divergence(neighbor.mass, particle.velocity, neighbor.velocity, particle.position, neighbor_position);
     }
 
-    let tension_force = calc_tension(tension_grad, tension_lap);
+    divergence[id] = velocity_divergence / max(density, info.density_epsilon);
+
+    let curvature_force = calc_tension(tension_grad, tension_lap);
+    let tension_force = mix(curvature_force, cohesion_force, info.cohesion_balance);
     surface[id] = length(tension_force);
-//    let forces = - pressure_force + viscous_coeficient * viscous_force + tension_force; 
-    let forces = pressure_force + viscous_coeficient * viscous_force + tension_force;
+//    let forces = - pressure_force + info.viscosity * viscous_force + tension_force; 
+    let forces = pressure_force + info.viscosity * viscous_force + tension_force;
 
     // calculate acceleration 
-    let g = vec2(0f, -0.1f);
+    let g = info.gravity;
     var acceleration = vec2(0f);
 
-    if (density != 0f) {
-        acceleration = forces / density;
-    }
+    acceleration = forces / max(density, info.density_epsilon);
 
     // calculate velocity
     let time_factor = 1f;
     let time = info.time_step * time_factor;
 
     acceleration += g;
-    particle.velocity += acceleration * time;
+    particle.acceleration = acceleration;
+
+    if (info.integrator == LEAPFROG) {
+        // finish the kick of the last step and kick the half of this one, 
+        // averaging the steps keeps it consistent when the time step changes
+        particle.velocity += acceleration * 0.5f * (info.last_time_step + time);
+    } else {
+        particle.velocity += acceleration * time;
+    }
+
+    // uniform scaling, so the damping has no preferred direction
+    particle.velocity *= max(1f - info.damping * time, 0f);
 
     // check for collisions 
     var new_pos = particle.position + particle.velocity * time;
@@ -234,29 +398,70 @@ fn main(
         }
 
         let neighbor = ins[j];
+        if (neighbor.alive == 0u) {
+            continue;
+        }
 
-        if (distance(new_pos, neighbor.position) <= 0.2f){
-            let normal = new_pos - neighbor.position;
+        let neighbor_position = periodic_image(new_pos, neighbor.position);
+
+        if (distance(new_pos, neighbor_position) <= 0.2f){
+            let normal = new_pos - neighbor_position;
             // todo: fix particle bouncing
-            particle.velocity = reflect(particle.velocity, normalize(normal));
+            particle.velocity = reflect(particle.velocity, safe_normalize(normal));
             new_pos += normal * (0.4f - length(normal));
         }
     }
-    if (new_pos.y <= 0f) {
-        particle.velocity = reflect(particle.velocity, vec2(0f, 1f));
-        new_pos.y += 0f - new_pos.y;
+
+    // resolve the domain faces
+    let extent = info.bounds_max - info.bounds_min;
+
+    if (new_pos.x <= info.bounds_min.x) {
+        let kind = info.boundaries[0];
+
+        if (kind == PERIODIC) {
+            new_pos.x += extent.x;
+        } else if (kind == OPEN) {
+            particle.alive = 0u;
+        } else {
+            particle.velocity = reflect_wall(particle.velocity, vec2(1f, 0f), vec2(info.wall_velocities[0], 0f));
+            new_pos.x += info.bounds_min.x - new_pos.x;
+        }
     }
-    if (new_pos.y >= 20f) {
-        particle.velocity = reflect(particle.velocity, vec2(0f, -1f));
-        new_pos.y += 20f - new_pos.y;
+    if (new_pos.x >= info.bounds_max.x) {
+        let kind = info.boundaries[1];
+
+        if (kind == PERIODIC) {
+            new_pos.x -= extent.x;
+        } else if (kind == OPEN) {
+            particle.alive = 0u;
+        } else {
+            particle.velocity = reflect_wall(particle.velocity, vec2(-1f, 0f), vec2(info.wall_velocities[1], 0f));
+            new_pos.x += info.bounds_max.x - new_pos.x;
+        }
     }
-    if (new_pos.x <= -0.1f) {
-        particle.velocity = reflect(particle.velocity, vec2(1f, 0f));
-        new_pos.x += -0.1f - new_pos.x;
+    if (new_pos.y <= info.bounds_min.y) {
+        let kind = info.boundaries[2];
+
+        if (kind == PERIODIC) {
+            new_pos.y += extent.y;
+        } else if (kind == OPEN) {
+            particle.alive = 0u;
+        } else {
+            particle.velocity = reflect_wall(particle.velocity, vec2(0f, 1f), vec2(0f, info.wall_velocities[2]));
+            new_pos.y += info.bounds_min.y - new_pos.y;
+        }
     }
-    if (new_pos.x >= 25f) {
-        particle.velocity = reflect(particle.velocity, vec2(-1f, 0f));
-        new_pos.x += 25f - new_pos.x;
+    if (new_pos.y >= info.bounds_max.y) {
+        let kind = info.boundaries[3];
+
+        if (kind == PERIODIC) {
+            new_pos.y -= extent.y;
+        } else if (kind == OPEN) {
+            particle.alive = 0u;
+        } else {
+            particle.velocity = reflect_wall(particle.velocity, vec2(0f, -1f), vec2(0f, info.wall_velocities[3]));
+            new_pos.y += info.bounds_max.y - new_pos.y;
+        }
     }
 
     // calculate new position 
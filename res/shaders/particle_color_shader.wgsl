@@ -0,0 +1,60 @@
+struct Particle {
+    @location(0) position: vec2<f32>,
+    @location(1) velocity: vec2<f32>,
+    @location(2) mass: f32,
+    @location(3) density: f32,
+    @location(4) alive: u32,
+    @location(5) group: u32,
+    @location(6) acceleration: vec2<f32>,
+}
+
+struct Range {
+    @location(0) lower: f32,
+    @location(1) upper: f32,
+    @location(2) colormap: u32,
+}
+
+@group(0) @binding(0) var<storage, read> particles: array<Particle>;
+@group(0) @binding(1) var<storage, read_write> colors: array<vec4<f32>>;
+@group(0) @binding(2) var<storage> range: Range;
+
+let GRAYSCALE = 0u;
+let VIRIDIS   = 1u;
+
+// polynomial fit of the matplotlib viridis colormap
+fn viridis(t: f32) -> vec3<f32> {
+    let c0 = vec3<f32>( 0.2777273272f,  0.0054073445f,   0.3340998053f);
+    let c1 = vec3<f32>( 0.1050930431f,  1.4046135299f,   1.3845901626f);
+    let c2 = vec3<f32>(-0.3308618287f,  0.2148475595f,   0.0950951630f);
+    let c3 = vec3<f32>(-4.6342304990f, -5.7991009734f, -19.3324409563f);
+    let c4 = vec3<f32>( 6.2282699363f, 14.1799333668f,  56.6905526007f);
+    let c5 = vec3<f32>( 4.7763849977f,-13.7451453777f, -65.3530326334f);
+    let c6 = vec3<f32>(-5.4354558559f,  4.6458526122f,  26.3124352496f);
+
+    return c0 + t * (c1 + t * (c2 + t * (c3 + t * (c4 + t * (c5 + t * c6)))));
+}
+
+@compute @workgroup_size(64)
+fn main(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+) {
+    let id = global_id.x;
+
+    if (id >= arrayLength(&colors) || id >= arrayLength(&particles)) {
+        return;
+    }
+
+    let speed = length(particles[id].velocity);
+    var t = 0f;
+
+    if (range.upper > range.lower) {
+        t = clamp((speed - range.lower) / (range.upper - range.lower), 0f, 1f);
+    }
+
+    var color = vec3(t);
+    if (range.colormap == VIRIDIS) {
+        color = viridis(t);
+    }
+
+    colors[id] = vec4(color, 1f);
+}
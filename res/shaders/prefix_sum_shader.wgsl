@@ -0,0 +1,62 @@
+struct Info {
+    @location(0) count: u32,
+}
+
+@group(0) @binding(0) var<storage, read_write> data: array<u32>;
+@group(0) @binding(1) var<storage, read_write> sums: array<u32>;
+@group(0) @binding(2) var<storage> info: Info;
+
+let BLOCK_SIZE = 256u;
+
+var<workgroup> block: array<u32, 256>;
+
+// exclusive scan of every block (Hillis-Steele), the block totals are written into sums
+@compute @workgroup_size(256)
+fn scan(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+    @builtin(workgroup_id) group_id: vec3<u32>,
+) {
+    let id = global_id.x;
+    let local = local_id.x;
+
+    // elements past the count are scanned as zeros, but never written
+    var value = 0u;
+    if (id < info.count) {
+        value = data[id];
+    }
+
+    block[local] = value;
+    workgroupBarrier();
+
+    for (var offset = 1u; offset < BLOCK_SIZE; offset *= 2u) {
+        var sum = block[local];
+        if (local >= offset) {
+            sum += block[local - offset];
+        }
+
+        workgroupBarrier();
+        block[local] = sum;
+        workgroupBarrier();
+    }
+
+    if (id < info.count) {
+        data[id] = block[local] - value;
+    }
+    if (local == BLOCK_SIZE - 1u) {
+        sums[group_id.x] = block[local];
+    }
+}
+
+// add the scanned block totals to the elements of the blocks
+@compute @workgroup_size(256)
+fn add(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(workgroup_id) group_id: vec3<u32>,
+) {
+    let id = global_id.x;
+
+    if (id < info.count) {
+        data[id] += sums[group_id.x];
+    }
+}
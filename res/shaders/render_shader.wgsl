@@ -3,15 +3,58 @@ struct Particle {
     @location(1) velocity: vec2<f32>,
     @location(2) mass: f32,
     @location(3) density: f32,
+    @location(4) alive: u32,
+    @location(5) group: u32,
+    @location(6) acceleration: vec2<f32>,
+}
+
+// same as the fluid shader
+struct Data {
+    @location(0) time_step: f32,
+    @location(1) width: u32,
+    @location(2) bounds_min: vec2<f32>,
+    @location(3) bounds_max: vec2<f32>,
+    @location(4) boundaries: array<u32, 4>, // left, right, bottom, top
+    @location(5) density_epsilon: f32,
+    @location(6) wall_velocities: array<f32, 4>, // left, right, bottom, top
+    @location(7) surface_tension: f32,
+    @location(8) cohesion_balance: f32, // 0 only curvature, 1 only cohesion
+    @location(9) max_cohesion: f32,
+    @location(10) support_radius: f32,
+    @location(11) integrator: u32,
+    @location(12) gravity: vec2<f32>,
+    @location(13) last_time_step: f32,
+    @location(14) damping: f32,
+    @location(15) viscosity: f32,
 }
 
 @group(0) @binding(0) var out_texture: texture_storage_2d<rgba8unorm, write>;
 @group(0) @binding(1) var<storage> particles: array<Particle>;
 @group(0) @binding(2) var<storage> mode: u32;
 @group(0) @binding(3) var<storage> surface: array<f32>;
+@group(0) @binding(4) var<storage> divergence: array<f32>;
+@group(0) @binding(5) var<storage> colors: array<vec4<f32>>; // speed colors
+@group(0) @binding(6) var<storage> info: Data; // fluid info, bounds and support radius
 
 let background_color = vec3<f32>(0.4f, 0.5f, 0.6f);
 let box_color = vec3<f32>(0.8f, 0.8f, 0.8f);
+let box_margin = 0.5f;
+
+// hash the id into a color, same as id_to_color on the cpu
+fn id_to_color(id: u32) -> vec3<f32> {
+    var x = id;
+    x ^= x >> 16u;
+    x *= 0x7feb352du;
+    x ^= x >> 15u;
+    x *= 0x846ca68bu;
+    x ^= x >> 16u;
+
+    return vec3<f32>(
+        f32(x & 0xffu),
+        f32((x >> 8u) & 0xffu),
+        f32((x >> 16u) & 0xffu),
+    ) / 255f;
+}
 
 @compute @workgroup_size(1, 1)
 fn main(
@@ -34,10 +77,14 @@ fn main(
 
     let position = vec2<f32>(color.xy);
     let particles_len = i32(arrayLength(&particles));
-    var closest: f32 = distance(particles[0].position, position);
+    var closest: f32 = 1e10f;
     var closest_idx: f32 = 0f;
 
     for (var i: i32 = 0; i < particles_len; i++ ) {
+        if (particles[i].alive == 0u) {
+            continue;
+        }
+
         let dist = distance(particles[i].position, position);
 
         if (dist < closest) {
@@ -51,32 +98,32 @@ fn main(
     var color = vec3(particle.density - 0.5f); 
 
     if (mode == 0u) {
-        color = vec3(
-            dst * 0.7f * closest_idx / 16f, 
-            closest_idx / 16f, 
-            dst
-        );
+        color = id_to_color(u32(closest_idx));
     } else if (mode == 2u) {
-        color = vec3(abs(particle.velocity) * 1f, 0f); 
+        color = colors[i32(closest_idx)].rgb;
     } else if (mode == 3u) {
         let surf = surface[i32(closest_idx)] * 100f;
         color = vec3(0f, surf, surf);
+    } else if (mode == 4u) {
+        // compression in red, expansion in blue
+        let div = divergence[i32(closest_idx)] * 10f;
+        color = vec3(max(-div, 0f), 0f, max(div, 0f));
+    } else if (mode == 5u) {
+        let cell = vec2<i32>(floor(particle.position / info.support_radius));
+        color = id_to_color(u32(cell.x) * 73856093u ^ u32(cell.y) * 19349663u);
+    } else if (mode == 6u && particle.group != 0u) {
+        // untagged particles keep the density color
+        color = id_to_color(particle.group);
     }
 
     if (dst <= 0f) {
         color = box_color;
     }
     
-    if (position.y < -0.5f) {
-        color = background_color;
-    }
-    if (position.y > 20.5f) {
-        color = background_color;
-    }
-    if (position.x < -0.6f) {
+    if (any(position < info.bounds_min - box_margin)) {
         color = background_color;
     }
-    if (position.x > 25.5f) {
+    if (any(position > info.bounds_max + box_margin)) {
         color = background_color;
     }
 
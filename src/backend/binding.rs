@@ -1,4 +1,4 @@
-use std::{rc::Rc, ops::Deref, any::Any};
+use std::{rc::Rc, ops::Deref};
 
 use crate::backend::FORMAT;
 
@@ -75,6 +75,7 @@ impl Visibility {
     }
 }
 
+#[derive(Copy, Clone, Debug)]
 pub enum ResourceType {
     Texture,
     Buffer,
@@ -85,7 +86,7 @@ pub enum ResourceType {
 impl ResourceType {
     /// Check if two types are equal
     pub fn equals(&self, other: Self) -> bool {
-        self.type_id() == other.type_id()
+        std::mem::discriminant(self) == std::mem::discriminant(&other)
     }
 }
 
@@ -93,11 +94,17 @@ pub trait Resource {
     /// get bind group layout entry of this resource
     fn get_layout(&self, binding: u32, visibility: Visibility) -> wgpu::BindGroupLayoutEntry;
 
-    /// get binding resource of this resource
-    fn get_resource(&self) -> wgpu::BindingResource;
+    /// get binding resource of this resource, it borrows the resource (e.g. the cached 
+    /// texture view) so the resource has to outlive the bind group creation
+    fn get_resource<'a>(&'a self) -> wgpu::BindingResource<'a>;
 
     /// return type of the data contained
     fn get_type(&self) -> ResourceType;
+
+    /// get device features needed to bind this resource
+    fn required_features(&self) -> wgpu::Features {
+        wgpu::Features::empty()
+    }
 }
 
 fn get_layout_entry(binding: u32, visibility: Visibility, ty: wgpu::BindingType) -> wgpu::BindGroupLayoutEntry {
@@ -109,20 +116,116 @@ fn get_layout_entry(binding: u32, visibility: Visibility, ty: wgpu::BindingType)
     }
 }
 
+/// Build the bind group layout together with the bind group from a list of resources, 
+/// the resources are bound in the order of the list unless remapped with with_bindings
+pub struct BindGroupBuilder<'a> {
+    resources: &'a [&'a dyn Resource],
+    bindings: Option<&'a [usize]>,
+    visibility: Visibility,
+    visibilities: Option<&'a [Visibility]>,
+}
+
+impl<'a> BindGroupBuilder<'a> {
+    pub fn new(resources: &'a [&'a dyn Resource], visibility: Visibility) -> Self {
+        BindGroupBuilder { resources, bindings: None, visibility, visibilities: None }
+    }
+
+    /// Set visibility of every resource instead of the shared one
+    pub fn with_visibilities(mut self, visibilities: &'a [Visibility]) -> Self {
+        self.visibilities = Some(visibilities);
+        self
+    }
+
+    /// Bind the resource at index i of the list to the binding bindings[i] in the bind group 
+    /// and its layout, there has to be a binding for every resource
+    pub fn with_bindings(mut self, bindings: &'a [usize]) -> Self {
+        self.bindings = Some(bindings);
+        self
+    }
+
+    /// Get binding of the resource at the index
+    fn get_binding(&self, index: usize) -> u32 {
+        match self.bindings {
+            Some(bindings) => bindings[index] as u32,
+            None => index as u32,
+        }
+    }
+
+    pub fn build(&self, device: &wgpu::Device) -> (wgpu::BindGroupLayout, wgpu::BindGroup) {
+        if let Some(bindings) = self.bindings {
+            assert_eq!(bindings.len(), self.resources.len(), "every resource needs exactly one binding");
+        }
+
+        let layouts = self.resources
+            .iter()
+            .enumerate()
+            .map(|(index, resource)| {
+                let visibility = self.visibilities
+                    .and_then(|visibilities| visibilities.get(index).copied())
+                    .unwrap_or(self.visibility);
+
+                resource.get_layout(self.get_binding(index), visibility)
+            })
+            .collect::<Vec<wgpu::BindGroupLayoutEntry>>();
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor { 
+            label: None, 
+            entries: layouts.as_slice(),
+        });
+
+        let bind_group = self.build_group(device, &layout);
+
+        (layout, bind_group)
+    }
+
+    /// Build only the bind group against an existing layout, the resources have to match it
+    pub fn build_group(&self, device: &wgpu::Device, layout: &wgpu::BindGroupLayout) -> wgpu::BindGroup {
+        let entries = self.resources
+            .iter()
+            .enumerate()
+            .map(|(index, resource)| wgpu::BindGroupEntry { 
+                binding: self.get_binding(index), 
+                resource: resource.get_resource(),
+            })
+            .collect::<Vec<wgpu::BindGroupEntry>>();
+
+        device.create_bind_group(&wgpu::BindGroupDescriptor { 
+            label: None, 
+            layout, 
+            entries: entries.as_slice() 
+        })
+    }
+}
+
 
 #[derive(Debug)]
 /// Contains texture and additional data
 pub struct Texture {
     texture: Rc<wgpu::Texture>,
-    view: wgpu::TextureView,
+    view: wgpu::TextureView, // cached, get_resource borrows it
 
     access: Access,
     dimension: Dimension,
+    format: wgpu::TextureFormat,
+    sample_type: wgpu::TextureSampleType, // used only when not storage
     is_storage: bool,
 }
 
+impl Deref for Texture {
+    type Target = wgpu::Texture;
+
+    fn deref(&self) -> &Self::Target {
+        &self.texture
+    }
+}
+
 impl Texture {
     pub fn new(texture: wgpu::Texture, access: Access, is_storage: bool) -> Self {
+        Self::new_with_format(texture, FORMAT, access, is_storage)
+    }
+
+    /// Wrap texture created with other format than the default one
+    pub fn new_with_format(texture: wgpu::Texture, format: wgpu::TextureFormat, access: Access, is_storage: bool) -> Self {
         let texture = Rc::new(texture);
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
 
@@ -131,22 +234,28 @@ impl Texture {
             view,
             access, 
             dimension: Dimension::D2, 
+            format,
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
             is_storage,
         }
     }
 
-    /// Swap texture
-    pub unsafe fn swap_texture(&mut self, mut new_texture: wgpu::Texture) {
-        let texture_ptr: *const wgpu::Texture = &*self.texture;
-        let texture_ptr = texture_ptr.cast_mut();
-        
-        let new_texture_ptr: *mut wgpu::Texture = &mut new_texture;
-        
-        // swap the textures
-        texture_ptr.swap(new_texture_ptr);
+    /// Set how the texture is sampled, e.g. Uint for integer id textures or 
+    /// non filterable float for R32Float, those can't be read by filtering sampler
+    pub fn with_sample_type(mut self, sample_type: wgpu::TextureSampleType) -> Self {
+        self.sample_type = sample_type;
+        self
+    }
 
-        // clean the old texture
-        new_texture_ptr.drop_in_place();
+    pub fn get_format(&self) -> wgpu::TextureFormat {
+        self.format
+    }
+
+    /// Swap texture, the view is recreated for the new texture while other handles 
+    /// keep the old one alive until they are dropped, so it's freed exactly once
+    pub fn swap_texture(&mut self, new_texture: wgpu::Texture) {
+        self.texture = Rc::new(new_texture);
+        self.view = self.texture.create_view(&wgpu::TextureViewDescriptor::default());
     }
 
     /// Get separate view of this texture data, and you can specify texture access data 
@@ -159,6 +268,8 @@ impl Texture {
             view,
             access: data.0, 
             dimension: data.1,
+            format: self.format,
+            sample_type: self.sample_type,
             is_storage: data.2,
         }
     }
@@ -169,12 +280,12 @@ impl Resource for Texture {
         let ty = if self.is_storage {
                 wgpu::BindingType::StorageTexture { 
                     access: self.access.to_wgpu(), 
-                    format: FORMAT, 
+                    format: self.format, 
                     view_dimension: self.dimension.to_view(),
                 }
             } else {
                 wgpu::BindingType::Texture { 
-                    sample_type: wgpu::TextureSampleType::Float { filterable: true }, // todo: parametrize
+                    sample_type: self.sample_type,
                     view_dimension: self.dimension.to_view(), 
                     multisampled: false
                 }
@@ -183,13 +294,20 @@ impl Resource for Texture {
         get_layout_entry(binding, visibility, ty)
     }
     
-    fn get_resource(&self) -> wgpu::BindingResource {
+    fn get_resource<'a>(&'a self) -> wgpu::BindingResource<'a> {
         wgpu::BindingResource::TextureView(&self.view) 
     }
 
     fn get_type(&self) -> ResourceType {
         ResourceType::Texture
     }
+
+    fn required_features(&self) -> wgpu::Features {
+        match (self.is_storage, self.access) {
+            (true, Access::Read) | (true, Access::Both) => wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
+            _ => wgpu::Features::empty(),
+        }
+    }
 }
 
 
@@ -236,7 +354,7 @@ impl Resource for Buffer {
         get_layout_entry(binding, visibility, ty)
     }
 
-    fn get_resource(&self) -> wgpu::BindingResource {
+    fn get_resource<'a>(&'a self) -> wgpu::BindingResource<'a> {
         wgpu::BindingResource::Buffer(self.buffer.as_entire_buffer_binding())
     }
 
@@ -247,9 +365,84 @@ impl Resource for Buffer {
 
 
 
+#[derive(Copy, Clone, Debug)]
+/// Describe how the sampler reads the texture, build it up from the default 
+/// (clamped to edge with linear filtering)
+pub struct SamplerConfig {
+    pub address_mode: wgpu::AddressMode,
+    pub mag_filter: wgpu::FilterMode,
+    pub min_filter: wgpu::FilterMode,
+    pub mipmap_filter: wgpu::FilterMode,
+    pub compare: Option<wgpu::CompareFunction>,
+}
+
+impl Default for SamplerConfig {
+    fn default() -> Self {
+        SamplerConfig { 
+            address_mode: wgpu::AddressMode::ClampToEdge, 
+            mag_filter: wgpu::FilterMode::Linear, 
+            min_filter: wgpu::FilterMode::Linear, 
+            mipmap_filter: wgpu::FilterMode::Linear, 
+            compare: None,
+        }
+    }
+}
+
+impl SamplerConfig {
+    /// Set address mode of all axes, e.g. Repeat to tile the texture
+    pub fn address_mode(mut self, address_mode: wgpu::AddressMode) -> Self {
+        self.address_mode = address_mode;
+        self
+    }
+
+    pub fn filter(mut self, mag_filter: wgpu::FilterMode, min_filter: wgpu::FilterMode) -> Self {
+        self.mag_filter = mag_filter;
+        self.min_filter = min_filter;
+        self
+    }
+
+    pub fn mipmap_filter(mut self, mipmap_filter: wgpu::FilterMode) -> Self {
+        self.mipmap_filter = mipmap_filter;
+        self
+    }
+
+    /// Make it comparison sampler, e.g. for depth textures
+    pub fn compare(mut self, compare: wgpu::CompareFunction) -> Self {
+        self.compare = Some(compare);
+        self
+    }
+
+    /// Get binding type matching the config, only nearest filtering can sample non filterable formats
+    pub fn binding_type(&self) -> wgpu::SamplerBindingType {
+        let is_linear = [self.mag_filter, self.min_filter, self.mipmap_filter]
+            .contains(&wgpu::FilterMode::Linear);
+
+        match (self.compare, is_linear) {
+            (Some(_), _) => wgpu::SamplerBindingType::Comparison,
+            (None, true) => wgpu::SamplerBindingType::Filtering,
+            (None, false) => wgpu::SamplerBindingType::NonFiltering,
+        }
+    }
+
+    pub fn to_wgpu(&self) -> wgpu::SamplerDescriptor<'static> {
+        wgpu::SamplerDescriptor { 
+            label: None, 
+            address_mode_u: self.address_mode, 
+            address_mode_v: self.address_mode, 
+            address_mode_w: self.address_mode, 
+            mag_filter: self.mag_filter, 
+            min_filter: self.min_filter, 
+            mipmap_filter: self.mipmap_filter, 
+            compare: self.compare,
+            ..Default::default()
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Sampler {
     sampler: wgpu::Sampler,
+    ty: wgpu::SamplerBindingType,
 }
 
 impl Deref for Sampler {
@@ -262,17 +455,24 @@ impl Deref for Sampler {
 
 impl Sampler {
     pub fn new(sampler: wgpu::Sampler) -> Self {
-        Sampler { sampler }
+        Sampler { sampler, ty: wgpu::SamplerBindingType::Filtering }
+    }
+
+    /// Create sampler from the config, binding with the matching type
+    pub fn from_config(device: &wgpu::Device, config: SamplerConfig) -> Self {
+        let sampler = device.create_sampler(&config.to_wgpu());
+
+        Sampler { sampler, ty: config.binding_type() }
     }
 }
 
 impl Resource for Sampler {
     fn get_layout(&self, binding: u32, visibility: Visibility) -> wgpu::BindGroupLayoutEntry {
-        let ty = wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering);
+        let ty = wgpu::BindingType::Sampler(self.ty);
         get_layout_entry(binding, visibility, ty)
     }
 
-    fn get_resource(&self) -> wgpu::BindingResource {
+    fn get_resource<'a>(&'a self) -> wgpu::BindingResource<'a> {
         wgpu::BindingResource::Sampler(&self.sampler)
     }
 
@@ -281,3 +481,33 @@ impl Resource for Sampler {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sampler_binding_type_filtering() {
+        assert_eq!(SamplerConfig::default().binding_type(), wgpu::SamplerBindingType::Filtering);
+
+        // any linear filter needs filtering binding
+        let config = SamplerConfig::default()
+            .filter(wgpu::FilterMode::Nearest, wgpu::FilterMode::Nearest);
+        assert_eq!(config.binding_type(), wgpu::SamplerBindingType::Filtering);
+    }
+
+    #[test]
+    fn sampler_binding_type_non_filtering() {
+        let config = SamplerConfig::default()
+            .filter(wgpu::FilterMode::Nearest, wgpu::FilterMode::Nearest)
+            .mipmap_filter(wgpu::FilterMode::Nearest);
+
+        assert_eq!(config.binding_type(), wgpu::SamplerBindingType::NonFiltering);
+    }
+
+    #[test]
+    fn sampler_binding_type_comparison() {
+        let config = SamplerConfig::default().compare(wgpu::CompareFunction::LessEqual);
+
+        assert_eq!(config.binding_type(), wgpu::SamplerBindingType::Comparison);
+    }
+}
@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+use std::ops::Deref;
+
+use crate::backend::{State, Shader, Size, ComputePipeline, binding::{Resource, Visibility}};
+
+
+/// Own the backend state and share compiled shader modules between pipelines
+pub struct ComputeContext {
+    state: State,
+    shaders: HashMap<&'static str, Shader>,
+}
+
+impl Deref for ComputeContext {
+    type Target = State;
+
+    fn deref(&self) -> &Self::Target {
+        &self.state
+    }
+}
+
+impl ComputeContext {
+    pub fn new(state: State) -> Self {
+        ComputeContext { 
+            state, 
+            shaders: HashMap::new(),
+        }
+    }
+
+    /// Create new shader, every path is read and compiled only once
+    pub fn create_shader(
+        &mut self,
+        path: &'static str,
+        entry: &'static str,
+        visibility: Visibility,
+    ) -> Shader {
+        let state = &self.state;
+        let shader = self.shaders
+            .entry(path)
+            .or_insert_with(|| Shader::new(state, path, entry, visibility));
+
+        shader.new_from(entry, visibility)
+    }
+
+    /// Create new compute pipeline from the shader at path
+    pub fn create_pipeline(
+        &mut self,
+        path: &'static str,
+        entry: &'static str,
+        entries: Vec<Box<dyn Resource>>,
+        size: impl Into<Size<u32>>,
+        workgroup_size: Option<Size<u32>>,
+    ) -> ComputePipeline {
+        let mut shader = self.create_shader(path, entry, Visibility::COMPUTE);
+
+        for entry in entries {
+            shader.add_entry(entry);
+        }
+
+        ComputePipeline::new(&self.state, shader, size, workgroup_size)
+    }
+
+    pub fn get_state(&self) -> &State {
+        &self.state
+    }
+}
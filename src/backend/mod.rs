@@ -1,5 +1,7 @@
 pub mod binding;
 pub use binding::*;
+pub mod context;
+pub use context::*;
 pub mod pipelines;
 pub use pipelines::*;
 pub mod shader;
@@ -62,3 +64,18 @@ where T: num_traits::Unsigned + Copy, u32: From<T>
     }
 }
 
+impl<T> From<winit::dpi::PhysicalSize<T>> for Size<T>
+where T: num_traits::Unsigned + Copy, u32: From<T> 
+{
+    fn from(size: winit::dpi::PhysicalSize<T>) -> Self {
+        Size::from_physical(size)
+    }
+}
+
+impl<T> From<(T, T)> for Size<T>
+where T: num_traits::Unsigned + Copy, u32: From<T> 
+{
+    fn from(tuple: (T, T)) -> Self {
+        Size::from_tuple(tuple)
+    }
+}
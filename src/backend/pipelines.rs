@@ -1,13 +1,13 @@
 use std::rc::Rc;
+use std::io::{Error, ErrorKind};
 use wgpu::util::DeviceExt;
 
-use crate::backend::Shader;
+use crate::backend::{Shader, ShaderError};
 
 use crate::backend::state::*;
 use crate::backend::binding;
 use crate::backend::Size;
 
-use super::FORMAT;
 
 
 #[repr(C)]
@@ -25,6 +25,19 @@ impl Vertex {
         }
     }
 
+    /// Create vertex from position (3 floats) followed by uv (2 floats), 
+    /// errors if the slice has a different length
+    pub fn from_slice(data: &[f32]) -> Result<Self, Error> {
+        let data: [f32; 5] = data
+            .try_into()
+            .map_err(|_| Error::new(
+                ErrorKind::InvalidInput, 
+                format!("vertex needs 5 floats (position and uv), got {}", data.len())
+            ))?;
+
+        Ok(Vertex::new(data))
+    }
+
     pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
         wgpu::VertexBufferLayout {
             array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
@@ -63,8 +76,45 @@ const RECT: Rect = Rect {
     ],
 };
 
+#[derive(Copy, Clone, Debug)]
+/// Describe how the vertices are assembled into primitives, 
+/// the default draws the filled full screen rectangle
+pub struct RenderPipelineConfig {
+    pub topology: wgpu::PrimitiveTopology,
+    pub cull_mode: Option<wgpu::Face>,
+    pub front_face: wgpu::FrontFace,
+    pub polygon_mode: wgpu::PolygonMode,
+}
+
+impl Default for RenderPipelineConfig {
+    fn default() -> Self {
+        RenderPipelineConfig { 
+            topology: wgpu::PrimitiveTopology::TriangleList, 
+            cull_mode: None, 
+            front_face: wgpu::FrontFace::Ccw, 
+            polygon_mode: wgpu::PolygonMode::Fill,
+        }
+    }
+}
+
+impl RenderPipelineConfig {
+    /// Get device features needed by the polygon mode
+    pub fn required_features(&self) -> wgpu::Features {
+        match self.polygon_mode {
+            wgpu::PolygonMode::Fill  => wgpu::Features::empty(),
+            wgpu::PolygonMode::Line  => wgpu::Features::POLYGON_MODE_LINE,
+            wgpu::PolygonMode::Point => wgpu::Features::POLYGON_MODE_POINT,
+        }
+    }
+}
+
 pub struct RenderPipeline {
     texture: binding::Texture,
+    texture_entry: usize, // index of the texture in the fragment entries
+    is_owned: bool, // false for textures passed to from_texture
+    size: Size<u32>,
+    clear_color: Option<wgpu::Color>, // None keeps the previous frame
+    config: RenderPipelineConfig,
     _vertex: Shader,
     fragment: Shader,
 
@@ -76,17 +126,46 @@ pub struct RenderPipeline {
 }
 
 impl RenderPipeline {
-    pub fn new(state: &State, vertex: Shader, mut fragment: Shader) -> Self {
+    /// Create new pipeline, panics if the fragment entries don't match the shader, see try_new
+    pub fn new(state: &State, vertex: Shader, fragment: Shader) -> Self {
+        Self::try_new(state, vertex, fragment).unwrap()
+    }
+
+    /// Create new pipeline, fails if the fragment entries don't match the shader bindings
+    pub fn try_new(state: &State, vertex: Shader, fragment: Shader) -> Result<Self, Error> {
         // setup the inputs
             // setup generic inputs
         let texture = state.create_texture(
             state.size, 
             wgpu::TextureUsages::STORAGE_BINDING | 
-            wgpu::TextureUsages::TEXTURE_BINDING, 
+            wgpu::TextureUsages::TEXTURE_BINDING |
+            wgpu::TextureUsages::COPY_SRC, 
             binding::Access::Both,
             false
         );
 
+        let mut pipeline = Self::try_from_texture(state, vertex, fragment, texture)?;
+        pipeline.is_owned = true;
+
+        Ok(pipeline)
+    }
+
+    /// Create pipeline sampling the given texture instead of allocating its own, 
+    /// the texture needs TEXTURE_BINDING usage and is expected to be of the window size,
+    /// panics if the fragment entries don't match the shader, see try_from_texture
+    pub fn from_texture(state: &State, vertex: Shader, fragment: Shader, input: binding::Texture) -> Self {
+        Self::try_from_texture(state, vertex, fragment, input).unwrap()
+    }
+
+    /// Same as from_texture, but fails if the fragment entries don't match the shader bindings
+    pub fn try_from_texture(
+        state: &State, 
+        vertex: Shader, 
+        mut fragment: Shader, 
+        input: binding::Texture,
+    ) -> Result<Self, Error> {
+        let texture = input.get_view(Some((binding::Access::Read, binding::Dimension::D2, false)));
+
             // segup specific inputs
         fragment.create_sampler();
 
@@ -103,17 +182,44 @@ impl RenderPipeline {
         });
 
         // bind the generic inputs
+        let texture_entry = fragment.entries_len();
         fragment.add_entry(Box::new(texture.get_view(None)));
+        fragment.validate_bindings()?;
 
         // setup the pipeline 
-        let layout = state.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor { 
+        let config = RenderPipelineConfig::default();
+        let pipeline = Self::create_pipeline(state, &vertex, &mut fragment, config);
+
+        Ok(RenderPipeline { 
+            texture, 
+            texture_entry,
+            is_owned: false,
+            size: state.size,
+            clear_color: Some(wgpu::Color::BLACK),
+            config,
+            _vertex: vertex, 
+            fragment, 
+            vertex_buffer, 
+            index_buffer, 
+            pipeline, 
+            state: state.get_state() 
+        })
+    }
+
+    fn create_pipeline(
+        state: &StateData, 
+        vertex: &Shader, 
+        fragment: &mut Shader, 
+        config: RenderPipelineConfig
+    ) -> wgpu::RenderPipeline {
+        let device = &state.device;
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor { 
             label: None, 
-            bind_group_layouts: &[
-                fragment.get_layout().unwrap(),
-            ], 
+            bind_group_layouts: fragment.get_layouts().as_slice(), 
             push_constant_ranges: &[]
         });
-        let pipeline = state.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor { 
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor { 
             label: None, 
             layout: Some(&layout), 
             vertex: wgpu::VertexState { 
@@ -122,12 +228,12 @@ impl RenderPipeline {
                 buffers: &[Vertex::desc()] // vertex description
             }, 
             primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList, // todo
+                topology: config.topology,
                 strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: None,
+                front_face: config.front_face,
+                cull_mode: config.cull_mode,
                 unclipped_depth: false,
-                polygon_mode: wgpu::PolygonMode::Fill,
+                polygon_mode: config.polygon_mode,
                 conservative: false,
             },
             depth_stencil: None, 
@@ -138,7 +244,7 @@ impl RenderPipeline {
                     entry_point: fragment.entry_point, 
                     targets: &[Some(
                         wgpu::ColorTargetState {
-                            format: FORMAT, // todo FORMAT?
+                            format: state.format,
                             blend: Some(wgpu::BlendState::REPLACE),
                             write_mask: wgpu::ColorWrites::ALL,
                         }
@@ -146,17 +252,7 @@ impl RenderPipeline {
                 },
             ),
             multiview: None,
-        });
-
-        RenderPipeline { 
-            texture, 
-            _vertex: vertex, 
-            fragment, 
-            vertex_buffer, 
-            index_buffer, 
-            pipeline, 
-            state: state.get_state() 
-        }
+        })
     }
 
     /// Get a handle to the render texture
@@ -164,8 +260,80 @@ impl RenderPipeline {
         self.texture.get_view(Some((access, binding::Dimension::D2, is_storage)))
     }
 
+    /// Get device features needed by this pipeline
+    pub fn required_features(&self) -> wgpu::Features {
+        self._vertex.required_features() | self.fragment.required_features() | self.config.required_features()
+    }
+
+    /// Rebuild the pipeline with the config, e.g. PointList topology for drawing the vertices 
+    /// as points, fails if the device lacks the feature needed by the polygon mode
+    pub fn set_config(&mut self, config: RenderPipelineConfig) -> Result<(), Error> {
+        let missing = config.required_features() - self.state.device.features();
+        if !missing.is_empty() {
+            return Err(Error::new(ErrorKind::Unsupported, format!("missing device features {:?}", missing)));
+        }
+
+        self.config = config;
+        self.pipeline = Self::create_pipeline(&self.state, &self._vertex, &mut self.fragment, config);
+
+        Ok(())
+    }
+
+    /// Get the size of the render texture
+    pub fn get_size(&self) -> Size<u32> {
+        self.size
+    }
+
+    /// Read the region of the render texture back to the cpu as tightly packed rows of pixels
+    pub fn read_texture_region(&self, origin: wgpu::Origin3d, size: Size<u32>) -> Result<Vec<u8>, Error> {
+        self.state.read_texture(&self.texture, origin, size)
+    }
+
+    /// Read the whole render texture back as rgba image, the texture has COPY_SRC usage 
+    /// unless the pipeline was created from a texture without it, formats other than 
+    /// 8 bit rgba/bgra are converted (float channels clamped to 0..1)
+    pub fn capture_frame(&self) -> Result<image::RgbaImage, Error> {
+        let data = self.read_texture_region(wgpu::Origin3d::ZERO, self.size)?;
+        let floats = |data: &[u8]| data
+            .chunks_exact(4)
+            .map(|bytes| (bytemuck::pod_read_unaligned::<f32>(bytes).clamp(0f32, 1f32) * 255f32) as u8)
+            .collect::<Vec<u8>>();
+
+        let pixels = match self.texture.get_format() {
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => data,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => data
+                .chunks_exact(4)
+                .flat_map(|pixel| [pixel[2], pixel[1], pixel[0], pixel[3]])
+                .collect(),
+            wgpu::TextureFormat::Rgba32Float => floats(&data),
+            wgpu::TextureFormat::R32Float => floats(&data)
+                .into_iter()
+                .flat_map(|value| [value, value, value, 255])
+                .collect(),
+            format => return Err(Error::new(ErrorKind::Unsupported, format!("can't capture {:?} texture", format))),
+        };
+
+        image::RgbaImage::from_raw(self.size.width, self.size.height, pixels)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "texture data doesn't match its size"))
+    }
+
+    /// Set the color the surface is cleared with before rendering, 
+    /// None loads the previous contents instead
+    pub fn set_clear_color(&mut self, color: Option<wgpu::Color>) {
+        self.clear_color = color;
+    }
+
     /// Creates render pass with instructions to clear display in place
-    fn begin_render_pass<'a>(encoder: &'a mut wgpu::CommandEncoder, view: &'a wgpu::TextureView) -> wgpu::RenderPass<'a> {
+    fn begin_render_pass<'a>(
+        encoder: &'a mut wgpu::CommandEncoder, 
+        view: &'a wgpu::TextureView, 
+        clear_color: Option<wgpu::Color>
+    ) -> wgpu::RenderPass<'a> {
+        let load = match clear_color {
+            Some(color) => wgpu::LoadOp::Clear(color),
+            None => wgpu::LoadOp::Load,
+        };
+
         encoder.begin_render_pass(&wgpu::RenderPassDescriptor { 
             label: Some("Render pipeline pass"), 
             color_attachments: &[
@@ -173,7 +341,7 @@ impl RenderPipeline {
                     view, 
                     resolve_target: None, 
                     ops: wgpu::Operations { 
-                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        load,
                         store: true, 
                     } 
                 }),
@@ -182,17 +350,31 @@ impl RenderPipeline {
         })
     }
 
-    /// !!! Not fully implemented, may cause bugs (bind group missalignments)
-    fn _resize(&mut self, size: Size<u32>) {
-        let usage = wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING;
-        let new_texture = self.state.create_raw_texture(size, usage);
-
-        unsafe {
-            self.texture.swap_texture(new_texture);
+    /// Replace the render texture with a new one of the size and format and rebuild the pipeline, 
+    /// handles to the old texture (e.g. in compute pipelines) have to be recreated with get_texture.
+    /// Fails for pipelines created with from_texture, their producer owns the texture
+    pub fn resize(&mut self, size: Size<u32>) -> Result<(), Error> {
+        if !self.is_owned {
+            return Err(Error::new(ErrorKind::Unsupported, "can't resize texture passed to from_texture"));
         }
 
+        let usage = wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_SRC;
+        let format = self.texture.get_format();
+        let texture = binding::Texture::new_with_format(
+            self.state.create_raw_texture_with_format(size, format, usage), 
+            format,
+            binding::Access::Both, 
+            false
+        );
+
+        self.fragment.set_entry(self.texture_entry, Box::new(texture.get_view(None))).unwrap();
         self.fragment.refresh_binding();
-        // todo: implement dynamic update of pipeline and its layout
+
+        self.texture = texture;
+        self.size = size;
+        self.pipeline = Self::create_pipeline(&self.state, &self._vertex, &mut self.fragment, self.config);
+
+        Ok(())
     }
 
     /// Plot input texture onto the surface
@@ -205,13 +387,15 @@ impl RenderPipeline {
         });
 
         {
-            let mut render_pass = RenderPipeline::begin_render_pass(&mut encoder, &view);
+            let mut render_pass = RenderPipeline::begin_render_pass(&mut encoder, &view, self.clear_color);
 
             render_pass.set_pipeline(&self.pipeline);
-            render_pass.set_bind_group(0, self.fragment.get_bind_group().unwrap(), &[]);
+            for (index, bind_group) in self.fragment.get_bind_groups().into_iter().enumerate() {
+                render_pass.set_bind_group(index as u32, bind_group, &[]);
+            }
             render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
             render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            render_pass.draw_indexed(0..6, 0, 0..2);
+            render_pass.draw_indexed(0..6, 0, 0..1);
         }
 
         self.state.queue.submit(std::iter::once(encoder.finish()));
@@ -220,6 +404,7 @@ impl RenderPipeline {
         Ok(())
     }
 
+    #[cfg(feature = "imgui")]
     pub fn render_with_ui(&mut self, renderer: &mut imgui_wgpu::Renderer, draw_data: &imgui::DrawData) -> Result<(), wgpu::SurfaceError> { 
         let output = self.state.surface.get_current_texture()?;
         let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
@@ -229,13 +414,15 @@ impl RenderPipeline {
         });
 
         {
-            let mut render_pass = RenderPipeline::begin_render_pass(&mut encoder, &view);
+            let mut render_pass = RenderPipeline::begin_render_pass(&mut encoder, &view, self.clear_color);
 
             render_pass.set_pipeline(&self.pipeline);
-            render_pass.set_bind_group(0, self.fragment.get_bind_group().unwrap(), &[]);
+            for (index, bind_group) in self.fragment.get_bind_groups().into_iter().enumerate() {
+                render_pass.set_bind_group(index as u32, bind_group, &[]);
+            }
             render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
             render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            render_pass.draw_indexed(0..6, 0, 0..2);
+            render_pass.draw_indexed(0..6, 0, 0..1);
 
             renderer
                 .render(draw_data, &self.state.queue, &self.state.device, &mut render_pass)
@@ -258,80 +445,205 @@ pub struct ComputePipeline {
     shader: Shader,
 
     workgroup_size: Size<u32>, // size of single work group
+    workgroup_size_z: u32,
     workgroups: Option<Size<u32>>, // work groups count
+    workgroups_z: u32,
     size: Size<u32>,
-    _size_z: Option<u32>,
+    size_z: Option<u32>, // None for 2D dispatch
+    push_constant_size: u32, // bytes, 0 without push constants
 }
 
 impl ComputePipeline {
-    pub fn new(state: &State, mut shader: Shader, size: Size<u32>, workgroup_size: Option<Size<u32>>) -> Self {
+    /// Create new pipeline, panics if the entries don't match the shader, see try_new
+    pub fn new(state: &State, shader: Shader, size: impl Into<Size<u32>>, workgroup_size: Option<Size<u32>>) -> Self {
+        Self::try_new(state, shader, size, workgroup_size).unwrap()
+    }
+
+    /// Create new pipeline, fails if the entries don't match the shader bindings
+    pub fn try_new(
+        state: &State, 
+        shader: Shader, 
+        size: impl Into<Size<u32>>, 
+        workgroup_size: Option<Size<u32>>,
+    ) -> Result<Self, Error> {
+        Self::try_new_3d(state, shader, size, None, workgroup_size, None)
+    }
+
+    /// Create pipeline dispatching over the 3D grid of size x depth, without depth it's the 
+    /// same as new, the workgroup depth defaults to 1, panics on error, see try_new_3d
+    pub fn new_3d(
+        state: &State, 
+        shader: Shader, 
+        size: impl Into<Size<u32>>, 
+        depth: Option<u32>, 
+        workgroup_size: Option<Size<u32>>, 
+        workgroup_depth: Option<u32>,
+    ) -> Self {
+        Self::try_new_3d(state, shader, size, depth, workgroup_size, workgroup_depth).unwrap()
+    }
+
+    /// Same as new_3d, but fails if the entries don't match the shader bindings 
+    /// or the workgroup depth is zero
+    pub fn try_new_3d(
+        state: &State, 
+        mut shader: Shader, 
+        size: impl Into<Size<u32>>, 
+        depth: Option<u32>, 
+        workgroup_size: Option<Size<u32>>, 
+        workgroup_depth: Option<u32>,
+    ) -> Result<Self, Error> {
+        let size = size.into();
         let workgroup_size = workgroup_size.unwrap_or(Size { width: 8u32, height: 8u32 });
+        if workgroup_depth == Some(0) {
+            return Err(Error::new(ErrorKind::InvalidInput, "workgroup depth has to be at least 1"));
+        }
 
-        let layout = state.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor { 
-            label: None, 
-            bind_group_layouts: &[
-                shader.get_layout().unwrap()
-            ],
-            push_constant_ranges: &[]
-        });
+        shader.validate_bindings()?;
 
-        let pipeline = state.device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-            label: None,
-            layout: Some(&layout),
-            module: shader.get_module(),
-            entry_point: shader.entry_point,
-        });
+        let push_constant_size = shader.push_constant_size()?;
+        if push_constant_size > 0 && !state.device.features().contains(wgpu::Features::PUSH_CONSTANTS) {
+            return Err(Error::new(ErrorKind::Unsupported, "shader uses push constants, they need the PUSH_CONSTANTS device feature"));
+        }
+
+        let pipeline = Self::create_pipeline(state, &mut shader, push_constant_size);
 
         let mut result = ComputePipeline { 
             state: state.get_state(), 
             pipeline, 
             shader, 
             workgroup_size,
+            workgroup_size_z: workgroup_depth.unwrap_or(1),
             workgroups: None,
+            workgroups_z: 1,
             size, 
-            _size_z: None 
+            size_z: depth,
+            push_constant_size,
         };
         result.compute_workgroups();
 
-        result
+        Ok(result)
     }
 
-    /// Regenerate the binding layout and pipeline
-    fn refresh_binding(&mut self) {
-        self.shader.refresh_binding();
+    /// Create pipeline dispatching over count elements along the x axis (e.g. a particle buffer), 
+    /// workgroup size defaults to 64, the shader has to bounds check the tail of the last workgroup
+    pub fn new_1d(state: &State, shader: Shader, count: u32, workgroup_size: Option<u32>) -> Self {
+        let workgroup_size = workgroup_size.unwrap_or(64);
+
+        Self::new(state, shader, Size::new(count, 1), Some(Size::new(workgroup_size, 1)))
+    }
 
-        let layout = self.state.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+    fn create_pipeline(state: &StateData, shader: &mut Shader, push_constant_size: u32) -> wgpu::ComputePipeline {
+        let push_constant_ranges = if push_constant_size > 0 {
+            vec![wgpu::PushConstantRange { 
+                stages: wgpu::ShaderStages::COMPUTE, 
+                range: 0..push_constant_size,
+            }]
+        } else {
+            vec![]
+        };
+
+        let layout = state.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: None,
-            bind_group_layouts: &[
-                self.shader.get_layout().unwrap()
-            ],
-            push_constant_ranges: &[]
+            bind_group_layouts: shader.get_layouts().as_slice(),
+            push_constant_ranges: push_constant_ranges.as_slice(),
         });
-        let pipeline = self.state.device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor { 
+
+        state.device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor { 
             label: None, 
             layout: Some(&layout), 
-            module: self.shader.get_module(), 
-            entry_point: self.shader.entry_point 
-        });
+            module: shader.get_module(), 
+            entry_point: shader.entry_point 
+        })
+    }
+
+    /// Regenerate the binding layout and pipeline
+    fn refresh_binding(&mut self) {
+        self.shader.refresh_binding();
+        self.pipeline = Self::create_pipeline(&self.state, &mut self.shader, self.push_constant_size);
+    }
+
+    /// Reserve size bytes of push constants for small per dispatch parameters passed to 
+    /// start_execute_with, the size used by the shader is reserved on creation, needs the 
+    /// PUSH_CONSTANTS feature (see State::new_with_features), without it pass the parameters 
+    /// through a storage buffer entry instead
+    pub fn set_push_constants(&mut self, size: u32) -> Result<(), Error> {
+        if !self.state.device.features().contains(wgpu::Features::PUSH_CONSTANTS) {
+            return Err(Error::new(ErrorKind::Unsupported, "push constants need the PUSH_CONSTANTS device feature"));
+        }
+
+        if size % wgpu::PUSH_CONSTANT_ALIGNMENT != 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput, 
+                format!("push constants size {} isn't a multiple of {}", size, wgpu::PUSH_CONSTANT_ALIGNMENT)
+            ));
+        }
+
+        let shader_size = self.shader.push_constant_size()?;
+        if size < shader_size {
+            return Err(Error::new(
+                ErrorKind::InvalidInput, 
+                format!("shader uses {} bytes of push constants, {} requested", shader_size, size)
+            ));
+        }
 
-        self.pipeline = pipeline;
+        let max_size = self.state.device.limits().max_push_constant_size;
+        if size > max_size {
+            return Err(Error::new(
+                ErrorKind::InvalidInput, 
+                format!("{} bytes of push constants requested, device supports {}", size, max_size)
+            ));
+        }
+
+        self.push_constant_size = size;
+        self.refresh_binding();
+
+        Ok(())
+    }
+
+    /// Get device features needed by this pipeline
+    pub fn required_features(&self) -> wgpu::Features {
+        let push_constants = if self.push_constant_size > 0 {
+            wgpu::Features::PUSH_CONSTANTS
+        } else {
+            wgpu::Features::empty()
+        };
+
+        self.shader.required_features() | push_constants
+    }
+
+    /// Reload the shader from its file and rebuild the pipeline, 
+    /// keeps the old pipeline running if the new source fails to compile
+    pub fn reload_shader(&mut self) -> Result<(), ShaderError> {
+        self.shader.reload()?;
+        self.refresh_binding();
+
+        Ok(())
     }
 
     /// Get the count of workgroups needed to be dispatched
     fn compute_workgroups(&mut self) {
         let workgroups = self.size.fit_other(self.workgroup_size);
+        let workgroups_z = self.size_z
+            .map(|depth| depth.div_ceil(self.workgroup_size_z))
+            .unwrap_or(1);
 
         self.workgroups = Some(workgroups);
+        self.workgroups_z = workgroups_z;
     }
 
-    /// Resize size of this pipeline, ! keep in mind if you are using this pipeline to 
-    /// render to texture you need to resize the texture first
+    /// Resize size of this pipeline, textures created by the shader (see Shader::create_storage_texture) 
+    /// are recreated at the new size, ! keep in mind textures bound from elsewhere (e.g. the render 
+    /// texture) have to be resized first and their new views set on the shader
     pub fn resize(&mut self, size: Size<u32>) { 
        self.size = size; 
+       self.shader.resize_textures(size);
        self.compute_workgroups();
-       self.shader.refresh_binding();
        self.refresh_binding();
-       // todo: implement TextureComputePipelines
+    }
+
+    /// Get handle to the texture created by the shader, see Shader::get_owned_texture
+    pub fn get_owned_texture(&self, index: usize) -> Option<binding::Texture> {
+        self.shader.get_owned_texture(index)
     }
 
     /// Swap shader resources and refresh pipeline binding
@@ -348,9 +660,43 @@ impl ComputePipeline {
         self.state.queue.submit(std::iter::once(encoder.finish()));
     }
 
+    /// Execute the shader with the push constants
+    pub fn execute_with(&mut self, push_constants: &[u8]) -> Result<(), Error> {
+        let encoder = self.start_execute_with(push_constants)?;
+        self.state.queue.submit(std::iter::once(encoder.finish()));
+
+        Ok(())
+    }
+
     /// Start execution
     pub fn start_execute(&mut self) -> wgpu::CommandEncoder {
-        let bind_group = self.shader.get_bind_group().unwrap();
+        self.encode(&[])
+    }
+
+    /// Start execution with the push constants, set up with set_push_constants, 
+    /// fails if they don't fit the reserved size or aren't a multiple of 4 bytes
+    pub fn start_execute_with(&mut self, push_constants: &[u8]) -> Result<wgpu::CommandEncoder, Error> {
+        let size = push_constants.len() as u32;
+        if size > self.push_constant_size {
+            return Err(Error::new(
+                ErrorKind::InvalidInput, 
+                format!("{} bytes of push constants passed, {} reserved with set_push_constants", size, self.push_constant_size)
+            ));
+        }
+
+        if size % wgpu::PUSH_CONSTANT_ALIGNMENT != 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput, 
+                format!("push constants size {} isn't a multiple of {}", size, wgpu::PUSH_CONSTANT_ALIGNMENT)
+            ));
+        }
+
+        Ok(self.encode(push_constants))
+    }
+
+    /// Record the dispatch, the push constants have to be validated
+    fn encode(&mut self, push_constants: &[u8]) -> wgpu::CommandEncoder {
+        let bind_groups = self.shader.get_bind_groups();
         let mut encoder = self.state.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: None,
         });
@@ -365,10 +711,43 @@ impl ComputePipeline {
             });
 
             compute_pass.set_pipeline(&self.pipeline);
-            compute_pass.set_bind_group(0, &bind_group, &[]);
-            compute_pass.dispatch_workgroups(workgroups.width, workgroups.height, 1);
+            for (index, bind_group) in bind_groups.into_iter().enumerate() {
+                compute_pass.set_bind_group(index as u32, bind_group, &[]);
+            }
+            if !push_constants.is_empty() {
+                compute_pass.set_push_constants(0, push_constants);
+            }
+            compute_pass.dispatch_workgroups(workgroups.width, workgroups.height, self.workgroups_z);
         }
 
+        // the next dispatch reads what this one wrote, the bind groups of both parities are prebuilt
+        self.shader.swap_ping_pong();
+
         encoder
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vertex_from_short_slice() {
+        let err = Vertex::from_slice(&[0f32; 4]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn vertex_from_exact_slice() {
+        let vertex = Vertex::from_slice(&[1f32, 2f32, 3f32, 4f32, 5f32]).unwrap();
+
+        assert_eq!(vertex.position, [1f32, 2f32, 3f32]);
+        assert_eq!(vertex.uv, [4f32, 5f32]);
+    }
+
+    #[test]
+    fn vertex_from_long_slice() {
+        let err = Vertex::from_slice(&[0f32; 6]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+}
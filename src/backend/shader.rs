@@ -10,23 +10,96 @@ use crate::backend::*;
 
 pub type Entries = Vec<Box<dyn Resource>>;
 
+#[derive(Copy, Clone, Debug, PartialEq)]
+/// How the storage texture both read and written by the shader is bound
+pub enum StorageTextureMode {
+    /// single texture_storage_2d<format, read_write> binding
+    ReadWrite,
+    /// texture_2d read with textureLoad followed by write only texture_storage_2d binding, 
+    /// the textures are swapped after every dispatch so the last output is read next time
+    PingPong,
+}
+
+#[derive(Debug)]
+/// Reason the shader source couldn't be (re)loaded
+pub enum ShaderError {
+    /// the source file couldn't be read
+    Io(Error),
+    /// the source doesn't compile or doesn't match the bound entries
+    Compile(String),
+}
+
+impl std::fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShaderError::Io(err)      => write!(f, "failed to read shader: {}", err),
+            ShaderError::Compile(err) => write!(f, "failed to compile shader: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+impl From<Error> for ShaderError {
+    fn from(err: Error) -> Self {
+        ShaderError::Io(err)
+    }
+}
+
 /// Group all shader metadata with the module
 ///     use new method to create new
 pub struct Shader {
     module: Rc<wgpu::ShaderModule>,
+    source: Rc<String>,
     pub entry_point: &'static str,
-    pub path: &'static str,
+    pub path: Option<&'static str>, // None for shaders created from source
     pub visibility: Visibility,
     
     entries: Entries,
+    visibilities: Vec<Visibility>, // visibility of every entry
     entry_layout: Option<Vec<usize>>,
 
     bind_group: Option<wgpu::BindGroup>,
+    swapped_bind_group: Option<wgpu::BindGroup>, // ping-pong pairs bound the other way around
     bind_layout: Option<wgpu::BindGroupLayout>,
+    groups: Vec<BindGroupData>, // groups following the entries (group 0)
+    textures: Vec<OwnedTexture>, // created by the shader, recreated on resize
+    is_swapped: bool, // the swapped bind group is the current one
     
     state: Rc<StateData>,
 }
 
+/// Texture created by the shader, bound as views at the entries
+struct OwnedTexture {
+    textures: Vec<binding::Texture>, // two for ping-pong, the first one is read when not swapped
+    entries: Vec<usize>,
+    views: Vec<(Access, bool)>, // access and is_storage of every entry
+    usage: wgpu::TextureUsages,
+}
+
+impl OwnedTexture {
+    /// Get the views bound at the entries, swapped rotates the textures of the ping-pong pair
+    fn get_views(&self, is_swapped: bool) -> Vec<binding::Texture> {
+        let shift = if is_swapped { 1 } else { 0 };
+
+        self.views
+            .iter()
+            .enumerate()
+            .map(|(index, (access, is_storage))| {
+                let texture = &self.textures[(index + shift) % self.textures.len()];
+                texture.get_view(Some((*access, Dimension::D2, *is_storage)))
+            })
+            .collect()
+    }
+}
+
+/// Bind group past the first one, bound once and never refreshed
+struct BindGroupData {
+    entries: Entries,
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+}
+
 impl Shader {
     /// Create new shader object
     pub fn new(
@@ -35,24 +108,43 @@ impl Shader {
         entry: &'static str, 
         visibility: Visibility, 
     ) -> Self {
-        let binding = fs::read_to_string(path).unwrap();
-        let source = binding.as_str();
+        let source = fs::read_to_string(path).unwrap();
+        let mut shader = Self::from_source(state, source, entry, visibility);
+        shader.path = Some(path);
+
+        shader
+    }
+
+    /// Create new shader object from the wgsl source, e.g. included with include_str!
+    pub fn from_source(
+        state: &State, 
+        source: String, 
+        entry: &'static str, 
+        visibility: Visibility, 
+    ) -> Self {
+        let source = Rc::new(source);
         let state = state.get_state();
         let module = Rc::new(state.device.create_shader_module(wgpu::ShaderModuleDescriptor { 
             label: None, 
-            source: wgpu::ShaderSource::Wgsl(source.into()),
+            source: wgpu::ShaderSource::Wgsl(source.as_str().into()),
         }));
 
         Shader {
             module,
+            source,
             entry_point: entry,
-            path,
+            path: None,
             visibility,
             entries: vec![],
+            visibilities: vec![],
             entry_layout: None,
             state,
             bind_group: None,
+            swapped_bind_group: None,
             bind_layout: None,
+            groups: vec![],
+            textures: vec![],
+            is_swapped: false,
         }
     }
 
@@ -63,36 +155,195 @@ impl Shader {
     ) -> Self {
         Shader {
             module: self.module.clone(),
+            source: self.source.clone(),
             entry_point: entry,
             path: self.path,
             visibility,
             entries: vec![],
+            visibilities: vec![],
             entry_layout: None,
             state: self.state.clone(),
             bind_group: None,
-            bind_layout: None
+            swapped_bind_group: None,
+            bind_layout: None,
+            groups: vec![],
+            textures: vec![],
+            is_swapped: false,
         }
     }
 
+    /// Read the source file again and recompile the module, on error the shader is left untouched, 
+    /// pipelines using the shader have to be rebuilt to pick up the new module
+    pub fn reload(&mut self) -> Result<(), ShaderError> {
+        let path = self.path
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "shader wasn't loaded from a file"))?;
+        let source = Rc::new(fs::read_to_string(path)?);
+
+        let old_source = std::mem::replace(&mut self.source, source);
+        if let Err(err) = self.validate_bindings() {
+            self.source = old_source;
+            return Err(ShaderError::Compile(err.to_string()));
+        }
+
+        // wgpu may still reject what naga accepted
+        self.state.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let module = self.state.device.create_shader_module(wgpu::ShaderModuleDescriptor { 
+            label: None, 
+            source: wgpu::ShaderSource::Wgsl(self.source.as_str().into()),
+        });
+
+        if let Some(err) = pollster::block_on(self.state.device.pop_error_scope()) {
+            self.source = old_source;
+            return Err(ShaderError::Compile(err.to_string()));
+        }
+
+        self.module = Rc::new(module);
+        Ok(())
+    }
+
+    /// Get count of the resources bound so far
+    pub fn entries_len(&self) -> usize {
+        self.entries.len()
+    }
+
     pub fn add_entry(&mut self, entry: Box<dyn Resource>) {
+        self.add_entry_with_visibility(entry, self.visibility);
+    }
+
+    /// Add entry visible from other shader stages than the shader itself
+    pub fn add_entry_with_visibility(&mut self, entry: Box<dyn Resource>, visibility: Visibility) {
+        // new entry takes the next binding, past the swapped ones
+        if let Some(layout) = &mut self.entry_layout {
+            layout.push(self.entries.len());
+        }
         self.entries.push(entry);
+        self.visibilities.push(visibility);
         self.refresh_binding();
     }
 
-    /// Create shader specific texture
+    /// Add the next bind group (starting at group 1) with the resources bound in order, 
+    /// returns the index of the group
+    pub fn add_group(&mut self, entries: Entries, visibility: Visibility) -> u32 {
+        let resources = entries
+            .iter()
+            .map(|entry| entry.as_ref())
+            .collect::<Vec<&dyn Resource>>();
+        let (layout, bind_group) = BindGroupBuilder::new(resources.as_slice(), visibility)
+            .build(&self.state.device);
+
+        drop(resources);
+        self.groups.push(BindGroupData { entries, layout, bind_group });
+
+        self.groups.len() as u32
+    }
+
+    /// Create shader specific texture, it's recreated by resize_textures
     pub fn create_texture(&mut self, size: Size<u32>, usage: wgpu::TextureUsages, access: binding::Access, is_storage: bool) {
-        let texture_data = self.state.device.create_texture(&wgpu::TextureDescriptor { 
-            label: None,
-            size: size.into_extent(),
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: Dimension::D2.to_texture(),
-            format: FORMAT,
-            usage,
-        });
+        let texture = self.create_owned_texture(size, FORMAT, usage);
+        self.add_owned_texture(vec![texture], vec![(access, is_storage)], usage);
+    }
+
+    /// Get how the storage texture of the format can be both read and written, 
+    /// read_write storage needs the TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES feature
+    pub fn storage_texture_mode(&self, format: wgpu::TextureFormat) -> StorageTextureMode {
+        let has_feature = self.state.device
+            .features()
+            .contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES);
+        let is_read_write = self.state.adapter
+            .get_texture_format_features(format)
+            .flags
+            .contains(wgpu::TextureFormatFeatureFlags::STORAGE_READ_WRITE);
+
+        if has_feature && is_read_write {
+            StorageTextureMode::ReadWrite
+        } else {
+            StorageTextureMode::PingPong
+        }
+    }
+
+    /// Create shader specific storage texture both read and written by the shader, it takes 
+    /// one binding with read_write storage and two (read, write) with the ping-pong fallback, 
+    /// so the shader has to be written for the returned mode, see storage_texture_mode
+    pub fn create_storage_texture(&mut self, size: Size<u32>, format: wgpu::TextureFormat) -> StorageTextureMode {
+        let usage = wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING | 
+            wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::COPY_DST;
+        let mode = self.storage_texture_mode(format);
+        let views = match mode {
+            StorageTextureMode::ReadWrite => vec![(Access::Both, true)],
+            StorageTextureMode::PingPong => vec![(Access::Read, false), (Access::Write, true)],
+        };
+
+        let textures = views
+            .iter()
+            .map(|_| self.create_owned_texture(size, format, usage))
+            .collect();
+        self.add_owned_texture(textures, views, usage);
+
+        mode
+    }
+
+    fn create_owned_texture(&self, size: Size<u32>, format: wgpu::TextureFormat, usage: wgpu::TextureUsages) -> binding::Texture {
+        let texture = self.state.create_raw_texture_with_format(size, format, usage);
+        // non filterable formats (e.g. R32Float) can still be read with textureLoad
+        binding::Texture::new_with_format(texture, format, Access::Read, false)
+            .with_sample_type(format.describe().sample_type)
+    }
+
+    fn add_owned_texture(&mut self, textures: Vec<binding::Texture>, views: Vec<(Access, bool)>, usage: wgpu::TextureUsages) {
+        let mut owned = OwnedTexture { textures, entries: vec![], views, usage };
+        for view in owned.get_views(false) {
+            owned.entries.push(self.entries.len());
+            self.add_entry(Box::new(view));
+        }
 
-        let texture = binding::Texture::new(texture_data, access, is_storage);
-        self.add_entry(Box::new(texture));
+        self.textures.push(owned);
+        self.refresh_binding();
+    }
+
+    /// Recreate the textures created by the shader at the new size and refresh the binding, 
+    /// the contents are lost
+    pub fn resize_textures(&mut self, size: Size<u32>) {
+        if self.textures.is_empty() {
+            return;
+        }
+
+        for index in 0..self.textures.len() {
+            let owned = &self.textures[index];
+            let textures = owned.textures
+                .iter()
+                .map(|texture| self.create_owned_texture(size, texture.get_format(), owned.usage))
+                .collect();
+            self.textures[index].textures = textures;
+
+            let owned = &self.textures[index];
+            for (entry, view) in owned.entries.iter().zip(owned.get_views(false)) {
+                self.entries[*entry] = Box::new(view);
+            }
+        }
+
+        self.is_swapped = false;
+        self.refresh_binding();
+    }
+
+    /// Swap the read and write textures of the ping-pong pairs by switching to the other 
+    /// bind group, returns false if there are none
+    pub fn swap_ping_pong(&mut self) -> bool {
+        if self.swapped_bind_group.is_none() {
+            return false;
+        }
+
+        self.is_swapped = !self.is_swapped;
+        true
+    }
+
+    /// Get handle to the texture created by the shader (as sampled texture), indexed in the 
+    /// creation order, the ping-pong pair returns the texture written by the last dispatch
+    pub fn get_owned_texture(&self, index: usize) -> Option<binding::Texture> {
+        let shift = if self.is_swapped { 1 } else { 0 };
+
+        self.textures
+            .get(index)
+            .map(|owned| owned.textures[shift % owned.textures.len()].get_view(None))
     }
 
     /// Create shader specific empty unmapped buffer
@@ -144,24 +395,29 @@ impl Shader {
 
     /// Create shader specific sampler (Linear filtering)
     pub fn create_sampler(&mut self) {
-        let address_mode = wgpu::AddressMode::ClampToEdge;
-        let filter_mode = wgpu::FilterMode::Linear;
-
-        let sampler_data = self.state.device.create_sampler(&wgpu::SamplerDescriptor { 
-            label: None, 
-            address_mode_u: address_mode, 
-            address_mode_v: address_mode, 
-            address_mode_w: address_mode, 
-            mag_filter: filter_mode, 
-            min_filter: filter_mode, 
-            mipmap_filter: filter_mode, 
-            ..Default::default()
-        });
+        self.create_sampler_with(SamplerConfig::default());
+    }
 
-        let sampler = binding::Sampler::new(sampler_data);
+    /// Create shader specific sampler with the config
+    pub fn create_sampler_with(&mut self, config: SamplerConfig) {
+        let sampler = binding::Sampler::from_config(&self.state.device, config);
         self.add_entry(Box::new(sampler));
     }
 
+    /// Replace the resource at the position keeping its binding, the types have to match
+    pub fn set_entry(&mut self, index: usize, entry: Box<dyn Resource>) -> Result<(), Error> {
+        let current = self.entries
+            .get(index)
+            .ok_or(Error::new(ErrorKind::NotFound, format!("no entry at {}", index)))?;
+
+        if !current.get_type().equals(entry.get_type()) {
+            return Err(Error::new(ErrorKind::Unsupported, "the two types aren't equal"));
+        }
+
+        self.entries[index] = entry;
+        Ok(())
+    }
+
     /// Swap resources at two positions (positions are refferencing the index in constant 
     /// entries array)
     pub fn swap_resources(&mut self, first: usize, second: usize) -> Result<(), Error> {
@@ -197,60 +453,211 @@ impl Shader {
         Ok(())
     }
 
-    /// refresh the bind group layout and bind group of this shader
-    pub fn refresh_binding(&mut self) {
-        let layouts = self.entries
+    /// Get entry bound at the binding of the group, the first group takes swapped resources into account
+    fn get_bound_entry(&self, group: usize, binding: usize) -> Option<&dyn Resource> {
+        if group > 0 {
+            return self.groups
+                .get(group - 1)
+                .and_then(|group| group.entries.get(binding))
+                .map(|entry| entry.as_ref());
+        }
+
+        let index = match &self.entry_layout {
+            Some(layout) => layout.iter().position(|bound| *bound == binding)?,
+            None => binding,
+        };
+
+        self.entries.get(index).map(|entry| entry.as_ref())
+    }
+
+    /// Parse the source and get the module with the info about the entry point
+    fn parse_module(&self) -> Result<(naga::Module, naga::valid::ModuleInfo, usize), Error> {
+        let module = naga::front::wgsl::parse_str(&self.source)
+            .map_err(|err| Error::new(ErrorKind::InvalidData, err.emit_to_string(&self.source)))?;
+        let info = naga::valid::Validator::new(naga::valid::ValidationFlags::empty(), naga::valid::Capabilities::all())
+            .validate(&module)
+            .map_err(|err| Error::new(ErrorKind::InvalidData, err.to_string()))?;
+
+        let index = module.entry_points
             .iter()
-            .enumerate()
-            .map(|(index, entry)| {
-                entry.get_layout(index as u32, self.visibility)
+            .position(|entry| entry.name == self.entry_point)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("missing entry point {}", self.entry_point)))?;
+
+        Ok((module, info, index))
+    }
+
+    /// Get size in bytes of the push constants used by the entry point, 0 without any
+    pub fn push_constant_size(&self) -> Result<u32, Error> {
+        let (module, info, index) = self.parse_module()?;
+        let function = info.get_entry_point(index);
+
+        let size = module.global_variables
+            .iter()
+            .filter(|(handle, variable)| {
+                variable.space == naga::AddressSpace::PushConstant && !function[*handle].is_empty()
             })
-            .collect::<Vec<wgpu::BindGroupLayoutEntry>>();
+            .map(|(_, variable)| module.types[variable.ty].inner.size(&module.constants))
+            .max()
+            .unwrap_or(0);
 
-        let layout = self.state.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor { 
-            label: None, 
-            entries: layouts.as_slice(),
-        });
+        Ok(size)
+    }
 
+    /// Check that the entries match the bindings used by the entry point in the shader source,
+    /// error names the first binding which is missing or of a different type, entries 
+    /// the entry point doesn't use only log a warning
+    pub fn validate_bindings(&self) -> Result<(), Error> {
+        let (module, info, index) = self.parse_module()?;
+        let function = info.get_entry_point(index);
 
-        let entries = self.entries.iter();
-        let resources = if self.entry_layout.is_none() {
-                entries
-                .enumerate()
-                .map(|(index, entry)| {
-                    let binding = index as u32;
-                    
-                    wgpu::BindGroupEntry { 
-                        binding, 
-                        resource: entry.get_resource(),
-                    }
-                })
-                .collect::<Vec<wgpu::BindGroupEntry>>()
-            } else {
-                entries
-                .zip(self.entry_layout.as_ref().unwrap().iter())
-                .map(|(entry, index)| {
-                    let binding = *index as u32;
-
-                    wgpu::BindGroupEntry { 
-                        binding, 
-                        resource: entry.get_resource() 
-                    }
+        let mut expected = module.global_variables
+            .iter()
+            .filter(|(handle, _)| !function[*handle].is_empty())
+            .filter_map(|(_, variable)| {
+                let binding = variable.binding.as_ref()?;
+                let ty = match variable.space {
+                    naga::AddressSpace::Uniform | naga::AddressSpace::Storage { .. } => ResourceType::Buffer,
+                    naga::AddressSpace::Handle => match module.types[variable.ty].inner {
+                        naga::TypeInner::Image { .. } => ResourceType::Texture,
+                        naga::TypeInner::Sampler { .. } => ResourceType::Sampler,
+                        _ => ResourceType::Other,
+                    },
+                    _ => ResourceType::Other,
+                };
+
+                Some((binding.group as usize, binding.binding as usize, ty))
+            })
+            .collect::<Vec<(usize, usize, ResourceType)>>();
+        expected.sort_by_key(|(group, binding, _)| (*group, *binding));
+
+        let groups = std::iter::once(&self.entries)
+            .chain(self.groups.iter().map(|group| &group.entries))
+            .collect::<Vec<&Entries>>();
+
+        for (group, binding, ty) in expected.iter() {
+            let entry = self.get_bound_entry(*group, *binding)
+                .ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("missing binding {} of group {}", binding, group)))?;
+
+            let entry_type = entry.get_type();
+            if !entry_type.equals(ResourceType::Other) && !entry_type.equals(*ty) {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput, 
+                    format!("binding {} of group {} expects {:?}, got {:?}", binding, group, ty, entry_type)
+                ));
+            }
+        }
 
-                })
-                .collect::<Vec<wgpu::BindGroupEntry>>()
+        // textures sampled by filtering sampler have to be filterable
+        let functions = module.functions
+            .iter()
+            .map(|(_, function)| function)
+            .chain(module.entry_points.iter().map(|entry| &entry.function));
+        for function in functions {
+            let global_binding = |expression: naga::Handle<naga::Expression>| match function.expressions[expression] {
+                naga::Expression::GlobalVariable(handle) => module.global_variables[handle].binding.clone(),
+                _ => None,
             };
+            let binding_type = |binding: &naga::ResourceBinding| self
+                .get_bound_entry(binding.group as usize, binding.binding as usize)
+                .map(|entry| entry.get_layout(binding.binding, self.visibility).ty);
+
+            for (_, expression) in function.expressions.iter() {
+                if let naga::Expression::ImageSample { image, sampler, .. } = expression {
+                    let (image, sampler) = match (global_binding(*image), global_binding(*sampler)) {
+                        (Some(image), Some(sampler)) => (image, sampler),
+                        _ => continue,
+                    };
+
+                    let is_filtering = matches!(
+                        binding_type(&sampler), 
+                        Some(wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering))
+                    );
+                    let is_filterable = match binding_type(&image) {
+                        Some(wgpu::BindingType::Texture { sample_type, .. }) => {
+                            sample_type == wgpu::TextureSampleType::Float { filterable: true }
+                        },
+                        _ => true,
+                    };
+
+                    if is_filtering && !is_filterable {
+                        return Err(Error::new(
+                            ErrorKind::InvalidInput, 
+                            format!(
+                                "binding {} of group {} isn't filterable, but is sampled by filtering sampler at binding {}", 
+                                image.binding, image.group, sampler.binding
+                            )
+                        ));
+                    }
+                }
+            }
+        }
 
-        let bind_group = self.state.device.create_bind_group(&wgpu::BindGroupDescriptor { 
-            label: None, 
-            layout: &layout, 
-            entries: resources.as_slice() 
-        });
+        // entry lists can be shared by several stages, so entries this one doesn't use are fine
+        for (group, entries) in groups.iter().enumerate() {
+            let bindings = (0..entries.len()).map(|index| match &self.entry_layout {
+                Some(layout) if group == 0 => layout[index],
+                _ => index,
+            });
+
+            for extra in bindings.filter(|bound| expected.iter().all(|(g, binding, _)| *g != group || binding != bound)) {
+                tracing::warn!("binding {} of group {} isn't used by the entry point {}", extra, group, self.entry_point);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// refresh the bind group layout and bind group of this shader
+    pub fn refresh_binding(&mut self) {
+        let resources = self.entries
+            .iter()
+            .map(|entry| entry.as_ref())
+            .collect::<Vec<&dyn Resource>>();
+
+        let mut builder = BindGroupBuilder::new(resources.as_slice(), self.visibility)
+            .with_visibilities(self.visibilities.as_slice());
+        if let Some(bindings) = &self.entry_layout {
+            builder = builder.with_bindings(bindings.as_slice());
+        }
+
+        let (layout, bind_group) = builder.build(&self.state.device);
+
+        // same layout, only the views of the ping-pong pairs are rotated
+        let swapped = self.textures
+            .iter()
+            .filter(|owned| owned.textures.len() > 1)
+            .flat_map(|owned| owned.entries.iter().copied().zip(owned.get_views(true)))
+            .collect::<Vec<(usize, binding::Texture)>>();
+
+        self.swapped_bind_group = if swapped.is_empty() {
+            None
+        } else {
+            let mut resources = resources;
+            for (entry, view) in swapped.iter() {
+                resources[*entry] = view;
+            }
+
+            let mut builder = BindGroupBuilder::new(resources.as_slice(), self.visibility);
+            if let Some(bindings) = &self.entry_layout {
+                builder = builder.with_bindings(bindings.as_slice());
+            }
+
+            Some(builder.build_group(&self.state.device, &layout))
+        };
 
         self.bind_layout = Some(layout);
         self.bind_group  = Some(bind_group);
     }
 
+    /// Get the bind group of the current ping-pong parity
+    fn current_bind_group(&self) -> Option<&wgpu::BindGroup> {
+        if self.is_swapped {
+            self.swapped_bind_group.as_ref()
+        } else {
+            self.bind_group.as_ref()
+        }
+    }
+
     pub fn get_binding(&mut self) 
         -> (Option<&wgpu::BindGroup>, Option<&wgpu::BindGroupLayout>) {
         if self.entries.len() == 0 {
@@ -261,7 +668,7 @@ impl Shader {
             self.refresh_binding();
         }
 
-        (self.bind_group.as_ref(), self.bind_layout.as_ref())
+        (self.current_bind_group(), self.bind_layout.as_ref())
     }
 
     pub fn get_bind_group(&mut self) -> Option<&wgpu::BindGroup> {
@@ -273,7 +680,7 @@ impl Shader {
             self.refresh_binding();
         }
 
-        self.bind_group.as_ref()
+        self.current_bind_group()
     }
     
     pub fn get_layout(&mut self) -> Option<&wgpu::BindGroupLayout> {
@@ -288,6 +695,41 @@ impl Shader {
         self.bind_layout.as_ref()
     }
 
+    /// Build group 0 if it's missing, without entries it's empty, so the other groups keep their index
+    fn build_first_group(&mut self) {
+        if self.bind_layout.is_none() && (!self.entries.is_empty() || !self.groups.is_empty()) {
+            self.refresh_binding();
+        }
+    }
+
+    /// Get layouts of all bind groups ordered by the group index
+    pub fn get_layouts(&mut self) -> Vec<&wgpu::BindGroupLayout> {
+        self.build_first_group();
+
+        self.bind_layout
+            .iter()
+            .chain(self.groups.iter().map(|group| &group.layout))
+            .collect()
+    }
+
+    /// Get all bind groups ordered by the group index
+    pub fn get_bind_groups(&mut self) -> Vec<&wgpu::BindGroup> {
+        self.build_first_group();
+
+        self.current_bind_group()
+            .into_iter()
+            .chain(self.groups.iter().map(|group| &group.bind_group))
+            .collect()
+    }
+
+    /// Get device features needed by the entries of this shader
+    pub fn required_features(&self) -> wgpu::Features {
+        self.entries
+            .iter()
+            .chain(self.groups.iter().flat_map(|group| group.entries.iter()))
+            .fold(wgpu::Features::empty(), |features, entry| features | entry.required_features())
+    }
+
     pub fn get_module(&self) -> &wgpu::ShaderModule {
         &self.module
     }
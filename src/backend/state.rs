@@ -1,5 +1,6 @@
 use std::ops::Deref;
 use std::rc::Rc;
+use std::io::Error;
 
 use bytemuck::NoUninit;
 use wgpu::util::DeviceExt;
@@ -12,6 +13,7 @@ pub struct StateData {
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
     pub size: Size<u32>,
+    pub format: wgpu::TextureFormat, // format of the surface
 }
 
 pub struct State {
@@ -19,15 +21,62 @@ pub struct State {
 }
 
 
+/// Get limits allowing buffers of the requested size, clamped to what the adapter supports,
+/// without the size the largest buffers the adapter supports are allowed
+fn request_limits(adapter: &wgpu::Adapter, buffer_size: Option<u64>, features: wgpu::Features) -> wgpu::Limits {
+    let supported = adapter.limits();
+    let mut limits = wgpu::Limits::downlevel_defaults();
+
+    let (binding_size, buffer_size) = match buffer_size {
+        Some(buffer_size) => (buffer_size.min(u32::MAX as u64) as u32, buffer_size),
+        None => (supported.max_storage_buffer_binding_size, supported.max_buffer_size),
+    };
+    if binding_size > supported.max_storage_buffer_binding_size || buffer_size > supported.max_buffer_size {
+        tracing::warn!(
+            "adapter {:?} supports only {} byte storage bindings and {} byte buffers, {} bytes requested", 
+            adapter.get_info().name,
+            supported.max_storage_buffer_binding_size, 
+            supported.max_buffer_size,
+            buffer_size
+        );
+    }
+
+    limits.max_storage_buffer_binding_size = binding_size
+        .min(supported.max_storage_buffer_binding_size)
+        .max(limits.max_storage_buffer_binding_size);
+    limits.max_buffer_size = buffer_size
+        .min(supported.max_buffer_size)
+        .max(limits.max_buffer_size);
+
+    if features.contains(wgpu::Features::PUSH_CONSTANTS) {
+        limits.max_push_constant_size = supported.max_push_constant_size;
+    }
+
+    limits
+}
+
+/// Pick the format of the surface, FORMAT if the adapter can present it, 
+/// otherwise the format the adapter prefers
+fn surface_format(surface: &wgpu::Surface, adapter: &wgpu::Adapter) -> wgpu::TextureFormat {
+    let formats = surface.get_supported_formats(adapter);
+
+    if formats.contains(&FORMAT) {
+        FORMAT
+    } else {
+        formats.first().copied().unwrap_or(FORMAT)
+    }
+}
+
 /// init wgpu surface with my default values
 fn config_surface(
     surface: &wgpu::Surface, 
     device: &wgpu::Device, 
-    size: winit::dpi::PhysicalSize<u32>
+    size: winit::dpi::PhysicalSize<u32>,
+    format: wgpu::TextureFormat,
 ) {
     surface.configure(device, &wgpu::SurfaceConfiguration {
         usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-        format: FORMAT, // could request supported from adapter
+        format,
         width: size.width,
         height: size.height,
         present_mode: wgpu::PresentMode::Fifo,
@@ -38,6 +87,24 @@ fn config_surface(
 impl StateData {
     /// Initialize new backend logic devices
     pub async fn new(window: &winit::window::Window) -> Self {
+        Self::new_with_features(window, wgpu::Features::default()).await
+    }
+
+    /// Initialize new backend logic devices with additional features enabled, 
+    /// the features can be collected from pipelines with required_features, 
+    /// features unsupported by the adapter are left out with a warning
+    pub async fn new_with_features(window: &winit::window::Window, features: wgpu::Features) -> Self {
+        Self::create(window, features, None).await
+    }
+
+    /// Initialize new backend logic devices able to allocate storage buffers of buffer_size bytes, 
+    /// the size is clamped to the adapter limits with a warning
+    pub async fn new_with_limits(window: &winit::window::Window, features: wgpu::Features, buffer_size: u64) -> Self {
+        Self::create(window, features, Some(buffer_size)).await
+    }
+
+    /// Initialize the devices, without buffer size the adapter limits are used
+    async fn create(window: &winit::window::Window, features: wgpu::Features, buffer_size: Option<u64>) -> Self {
         let instance = wgpu::Instance::new(wgpu::Backends::PRIMARY);
         let surface = unsafe { instance.create_surface(window) };
         let adapter = instance.request_adapter(&wgpu::RequestAdapterOptions {
@@ -48,32 +115,41 @@ impl StateData {
         .await
         .unwrap();
 
+        // requesting features the adapter lacks fails, pipelines check the device features instead
+        let missing = features - adapter.features();
+        if !missing.is_empty() {
+            tracing::warn!("adapter {:?} doesn't support features {:?}", adapter.get_info().name, missing);
+        }
+        let features = features & adapter.features();
+
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor { 
                     label: Some("main device"),
-                    features: wgpu::Features::default(),
-                    limits: wgpu::Limits::downlevel_defaults(),
+                    features: wgpu::Features::default() | features,
+                    limits: request_limits(&adapter, buffer_size, features),
                 },
                 None
              )
             .await
             .unwrap();
          
-        config_surface(&surface, &device, window.inner_size());
+        let format = surface_format(&surface, &adapter);
+        config_surface(&surface, &device, window.inner_size(), format);
         
         StateData { 
             surface,
             adapter,
             device,
             queue,
-            size: Size::from_physical(window.inner_size())
+            size: Size::from_physical(window.inner_size()),
+            format,
         }
     }
 
     /// Resize window surface to the new size
     pub fn resize(&self, size: winit::dpi::PhysicalSize<u32>) {
-        config_surface(&self.surface, &self.device, size);
+        config_surface(&self.surface, &self.device, size, self.format);
     }
 
     /// Create new raw texture with my custom default params
@@ -81,6 +157,16 @@ impl StateData {
         &self,
         size: Size<u32>,
         usage: wgpu::TextureUsages,
+    ) -> wgpu::Texture {
+        self.create_raw_texture_with_format(size, FORMAT, usage)
+    }
+
+    /// Create new raw texture of the format
+    pub fn create_raw_texture_with_format(
+        &self,
+        size: Size<u32>,
+        format: wgpu::TextureFormat,
+        usage: wgpu::TextureUsages,
     ) -> wgpu::Texture {
         self.device.create_texture(&wgpu::TextureDescriptor {
             label: None,
@@ -88,10 +174,112 @@ impl StateData {
             mip_level_count: 1,
             sample_count: 1,
             dimension: binding::Dimension::D2.to_texture(),
-            format: FORMAT,
+            format,
             usage,
         })
     }
+
+    /// Map the staging buffer for reading and wait until it's mapped
+    fn map_staging(&self, staging: &wgpu::Buffer) -> Result<(), Error> {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        staging.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+            sender.send(result).ok();
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+
+        receiver
+            .recv()
+            .map_err(Error::other)?
+            .map_err(Error::other)
+    }
+
+    /// Read the contents of the buffer back to the cpu through mappable staging buffer,
+    /// the source buffer has to be created with COPY_SRC usage, copies have to be multiples 
+    /// of COPY_BUFFER_ALIGNMENT so the size is rounded up and the source has to fit the rounded size
+    pub fn read_buffer(&self, src: &wgpu::Buffer, size: u64) -> Result<Vec<u8>, Error> {
+        let alignment = wgpu::COPY_BUFFER_ALIGNMENT;
+        let aligned_size = size.next_multiple_of(alignment);
+
+        let staging = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Staging buffer"),
+            size: aligned_size as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Read buffer command encoder"),
+        });
+        encoder.copy_buffer_to_buffer(src, 0, &staging, 0, aligned_size);
+        self.queue.submit(std::iter::once(encoder.finish()));
+        self.map_staging(&staging)?;
+
+        let mut data = staging.slice(..).get_mapped_range().to_vec();
+        staging.unmap();
+
+        data.truncate(size as usize);
+        Ok(data)
+    }
+
+    /// Read count elements of type T from the start of the buffer, see read_buffer
+    pub fn read_buffer_as<T: bytemuck::Pod>(&self, src: &wgpu::Buffer, count: usize) -> Result<Vec<T>, Error> {
+        let size = std::mem::size_of::<T>();
+        let bytes = self.read_buffer(src, (count * size) as u64)?;
+
+        Ok(bytes
+            .chunks_exact(size)
+            .map(bytemuck::pod_read_unaligned)
+            .collect())
+    }
+
+    /// Read the region of the texture back to the cpu, returned rows are tightly packed,
+    /// the texture has to be created with COPY_SRC usage
+    pub fn read_texture(&self, texture: &binding::Texture, origin: wgpu::Origin3d, size: Size<u32>) -> Result<Vec<u8>, Error> {
+        let bytes_per_pixel = texture.get_format().describe().block_size as u32;
+        let unpadded_bytes_per_row = size.width * bytes_per_pixel;
+        let alignment = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = unpadded_bytes_per_row.next_multiple_of(alignment);
+
+        let staging = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Staging texture buffer"),
+            size: (padded_bytes_per_row * size.height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Read texture command encoder"),
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &**texture,
+                mip_level: 0,
+                origin,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &staging,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(padded_bytes_per_row),
+                    rows_per_image: std::num::NonZeroU32::new(size.height),
+                },
+            },
+            size.into_extent(),
+        );
+        self.queue.submit(std::iter::once(encoder.finish()));
+        self.map_staging(&staging)?;
+
+        // strip the row padding
+        let data = staging.slice(..)
+            .get_mapped_range()
+            .chunks(padded_bytes_per_row as usize)
+            .flat_map(|row| row[..unpadded_bytes_per_row as usize].to_vec())
+            .collect::<Vec<u8>>();
+        staging.unmap();
+
+        Ok(data)
+    }
 }
 
 impl Deref for State {
@@ -104,7 +292,22 @@ impl Deref for State {
 
 impl State {
     pub async fn new(window: &winit::window::Window) -> Self {
-        let state = StateData::new(window).await;
+        Self::new_with_features(window, wgpu::Features::default()).await
+    }
+
+    /// Create new state with additional device features
+    pub async fn new_with_features(window: &winit::window::Window, features: wgpu::Features) -> Self {
+        let state = StateData::new_with_features(window, features).await;
+        let state = Rc::new(state);
+        
+        State {
+            state
+        }
+    }
+
+    /// Create new state able to allocate storage buffers of buffer_size bytes
+    pub async fn new_with_limits(window: &winit::window::Window, features: wgpu::Features, buffer_size: u64) -> Self {
+        let state = StateData::new_with_limits(window, features, buffer_size).await;
         let state = Rc::new(state);
         
         State {
@@ -144,17 +347,21 @@ impl State {
         access: binding::Access,
         is_storage: bool,
     ) -> binding::Texture {
-        let texture = self.device.create_texture(&wgpu::TextureDescriptor{
-            label: None,
-            size: size.into_extent(),
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: binding::Dimension::D2.to_texture(),
-            format: FORMAT,
-            usage,
-        });
+        self.create_texture_with_format(size, FORMAT, usage, access, is_storage)
+    }
+
+    /// Create generic texture of the format, e.g. R32Float for scalar fields
+    pub fn create_texture_with_format(
+        &self, 
+        size: Size<u32>, 
+        format: wgpu::TextureFormat,
+        usage: wgpu::TextureUsages,
+        access: binding::Access,
+        is_storage: bool,
+    ) -> binding::Texture {
+        let texture = self.create_raw_texture_with_format(size, format, usage);
 
-        binding::Texture::new(texture, access, is_storage)
+        binding::Texture::new_with_format(texture, format, access, is_storage)
     }
 
     /// Create new empty unmapped buffer
@@ -0,0 +1,168 @@
+use std::rc::Rc;
+
+use crate::backend::*;
+
+
+#[derive(Copy, Clone, Debug)]
+/// Describe how the scalar values are mapped to colors
+pub enum ColormapKind {
+    Grayscale,
+    Viridis,
+}
+
+impl ColormapKind {
+    pub fn get(&self) -> u32 {
+        match *self {
+            ColormapKind::Grayscale => 0,
+            ColormapKind::Viridis   => 1,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ColormapInfo {
+    lower: f32,
+    upper: f32,
+    colormap: u32,
+}
+
+/// Post pass mapping the red channel of the input texture onto the colormap
+pub struct Colormap {
+    computer: ComputePipeline,
+    state: Rc<StateData>,
+
+    info: ColormapInfo,
+    info_buffer: Buffer,
+}
+
+impl Colormap {
+    /// Create new colormap pass, the input is loaded (not sampled) so it can't be a storage 
+    /// texture but can be non filterable (e.g. R32Float), the output has to be a writable 
+    /// storage texture of the given size
+    pub fn new(
+        state: &State, 
+        mut shader: Shader, 
+        input: Texture, 
+        output: Texture, 
+        size: Size<u32>, 
+        kind: ColormapKind,
+    ) -> Self {
+        let info = ColormapInfo {
+            lower: 0f32,
+            upper: 1f32,
+            colormap: kind.get(),
+        };
+
+        let info_buffer = state.create_buffer_init(
+            &[info], 
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST, 
+            Access::Read
+        );
+
+        // textureLoad doesn't filter, so any float format fits
+        let input = input.with_sample_type(wgpu::TextureSampleType::Float { filterable: false });
+        shader.add_entry(Box::new(input));
+        shader.add_entry(Box::new(output));
+        shader.add_entry(Box::new(info_buffer.get_binding(None)));
+
+        let computer = ComputePipeline::new(state, shader, size, None);
+
+        Colormap { 
+            computer, 
+            state: state.get_state(), 
+            info, 
+            info_buffer,
+        }
+    }
+
+    /// Set the values mapped onto the start and the end of the colormap
+    pub fn set_range(&mut self, lower: f32, upper: f32) {
+        self.info.lower = lower;
+        self.info.upper = upper;
+    }
+
+    pub fn set_kind(&mut self, kind: ColormapKind) {
+        self.info.colormap = kind.get();
+    }
+
+    /// Map the input texture onto the output
+    pub fn execute(&mut self) {
+        self.state.queue.write_buffer(&self.info_buffer, 0, bytemuck::cast_slice(&[self.info]));
+        self.computer.execute();
+    }
+}
+
+/// Per particle colors computed on the gpu from the particle speed
+pub struct ParticleColors {
+    computer: ComputePipeline,
+    state: Rc<StateData>,
+
+    info: ColormapInfo,
+    info_buffer: Buffer,
+    colors: Buffer,
+}
+
+impl ParticleColors {
+    /// Create new color pass over count particles of the buffer, 
+    /// the colors are written as rgba into the storage buffer with one entry per particle
+    pub fn new(
+        state: &State, 
+        mut shader: Shader, 
+        particles: &Buffer, 
+        count: u32, 
+        kind: ColormapKind,
+    ) -> Self {
+        let info = ColormapInfo {
+            lower: 0f32,
+            upper: 1f32,
+            colormap: kind.get(),
+        };
+
+        let info_buffer = state.create_buffer_init(
+            &[info], 
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST, 
+            Access::Read
+        );
+        let colors = state.create_buffer(
+            count as u64 * 16, 
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC, 
+            Access::Write
+        );
+
+        shader.add_entry(Box::new(particles.get_binding(Some((Access::Read, )))));
+        shader.add_entry(Box::new(colors.get_binding(Some((Access::Both, )))));
+        shader.add_entry(Box::new(info_buffer.get_binding(None)));
+
+        let computer = ComputePipeline::new_1d(state, shader, count, None);
+
+        ParticleColors { 
+            computer, 
+            state: state.get_state(), 
+            info, 
+            info_buffer,
+            colors,
+        }
+    }
+
+    /// Set the speeds mapped onto the start and the end of the colormap
+    pub fn set_range(&mut self, lower: f32, upper: f32) {
+        self.info.lower = lower;
+        self.info.upper = upper;
+    }
+
+    pub fn set_kind(&mut self, kind: ColormapKind) {
+        self.info.colormap = kind.get();
+    }
+
+    /// Buffer of the rgba colors, to be bound by the renderer
+    pub fn get_colors(&self) -> &Buffer {
+        &self.colors
+    }
+
+    /// Recompute the colors from the current particle velocities
+    pub fn execute(&mut self) {
+        self.state.queue.write_buffer(&self.info_buffer, 0, bytemuck::cast_slice(&[self.info]));
+        self.computer.execute();
+    }
+}
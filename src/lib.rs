@@ -1,40 +1,59 @@
 use std::rc::Rc;
+use std::io::{Error, ErrorKind};
+use std::time;
+#[cfg(feature = "imgui")]
 use window::init_window;
+#[cfg(feature = "imgui")]
 use winit::{event::Event, event::WindowEvent};
-use std::time;
 
 mod backend;
 pub use crate::backend::*;
+pub mod colormap;
+pub mod prefix_sum;
+pub mod recorder;
 
 
+#[cfg(feature = "imgui")]
 #[derive(Debug)]
 enum Mode {
     ID, 
     Density,
     Velocity,
     Surface,
+    Divergence,
+    Cell,
+    Group,
 }
 
+#[cfg(feature = "imgui")]
 impl Mode {
     pub fn next(&self) -> Self {
         match *self {
-            Mode::ID       => Mode::Density,
-            Mode::Density  => Mode::Velocity,
-            Mode::Velocity => Mode::Surface,
-            Mode::Surface  => Mode::ID,
+            Mode::ID         => Mode::Density,
+            Mode::Density    => Mode::Velocity,
+            Mode::Velocity   => Mode::Surface,
+            Mode::Surface    => Mode::Divergence,
+            Mode::Divergence => Mode::Cell,
+            Mode::Cell       => Mode::Group,
+            Mode::Group      => Mode::ID,
         }
     }
 
     pub fn get(&self) -> u32 {
         match *self {
-            Mode::ID       => 0,
-            Mode::Density  => 1,
-            Mode::Velocity => 2,
-            Mode::Surface  => 3,
+            Mode::ID         => 0,
+            Mode::Density    => 1,
+            Mode::Velocity   => 2,
+            Mode::Surface    => 3,
+            Mode::Divergence => 4,
+            Mode::Cell       => 5,
+            Mode::Group      => 6,
         }
     }
 }
 
+/// Run the interactive demo, needs the imgui feature
+#[cfg(feature = "imgui")]
 pub async fn run() {
     let (event_loop, window) = init_window();
     
@@ -63,8 +82,22 @@ pub async fn run() {
     shader.add_entry(Box::new(water.particles_in.get_binding(Some((Access::Read, )))));
     shader.add_entry(Box::new(mode_buffer.get_binding(None)));
     shader.add_entry(Box::new(water.surface.get_binding(None)));
+    shader.add_entry(Box::new(water.divergence.get_binding(None)));
+
+    // velocity coloring setup
+    let color_shader = Shader::new(&state, "./res/shaders/particle_color_shader.wgsl", "main", Visibility::COMPUTE);
+    let mut particle_colors = colormap::ParticleColors::new(
+        &state, 
+        color_shader, 
+        &water.particles_in, 
+        water.particle_count(), 
+        colormap::ColormapKind::Viridis
+    );
+    particle_colors.set_range(0f32, 2f32);
+    shader.add_entry(Box::new(particle_colors.get_colors().get_binding(Some((Access::Read, )))));
+    shader.add_entry(Box::new(water.info_buffer.get_binding(None)));
     
-    let mut compute = ComputePipeline::new(&state, shader, Size::from_physical(window.inner_size()), Some(Size::new(1, 1)));
+    let mut compute = ComputePipeline::new(&state, shader, window.inner_size(), Some(Size::new(1, 1)));
 
     // setup ui
     let mut ui_context = imgui::Context::create(); 
@@ -89,7 +122,7 @@ pub async fn run() {
     }]);
 
     let renderer_config = imgui_wgpu::RendererConfig {
-        texture_format: backend::FORMAT,
+        texture_format: state.format,
         ..Default::default()
     };
     
@@ -97,8 +130,12 @@ pub async fn run() {
 
     // initial update
     water.update();
+    particle_colors.execute();
     compute.execute();
 
+    let mut cursor = winit::dpi::PhysicalPosition::new(0f64, 0f64);
+    let mut picked: Option<usize> = None;
+
     let mut delta_s = time::Duration::ZERO;
     let mut last_frame = time::Instant::now();
     event_loop.run(move |event, _, control_flow| {
@@ -118,6 +155,9 @@ pub async fn run() {
                             state.resize(*new_inner_size);
                             set_ui_size(&window, &mut ui_context);
                         },
+                        WindowEvent::CursorMoved { position, .. } => {
+                            cursor = position;
+                        },
                         WindowEvent::MouseInput { state, button, .. } => {
                             match button {
                                 winit::event::MouseButton::Left => {
@@ -128,6 +168,11 @@ pub async fn run() {
                                         winit::event::ElementState::Released => {
                                             false
                                         }
+                                    };
+
+                                    if is_active {
+                                        let position = cursor_to_fluid(cursor, window.inner_size(), render_pipeline.get_size());
+                                        picked = water.pick(position);
                                     }
                                 },
                                 _ => {}
@@ -141,7 +186,6 @@ pub async fn run() {
                             } = input {
                                 mode = mode.next();
                                 state.queue.write_buffer(&mode_buffer, 0, bytemuck::cast_slice(&[mode.get()]));
-                                dbg!("changed mode", &mode);
                             }
                         }
                         _ => {}
@@ -152,6 +196,7 @@ pub async fn run() {
                 // update app
                 if is_active {
                     water.update();
+                    particle_colors.execute();
                 }
 
                 // app update
@@ -180,6 +225,7 @@ pub async fn run() {
                         .build(|| {
                             ui.text(format!("{:?} FPS, ({:?}micros)", fps, delta_s.as_micros()));
                             ui.text(format!("Mode: {:?}", &mode));
+                            ui.text(format!("Picked: {:?}", picked));
                         });
                 }
                 
@@ -203,6 +249,56 @@ pub async fn run() {
     });
 }
 
+/// Upload the particles into new storage buffer, readable back with download_particles
+pub fn upload_particles(state: &State, particles: &[Particle]) -> Buffer {
+    state.create_buffer_init(
+        particles, 
+        wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST, 
+        Access::Both
+    )
+}
+
+/// Download count particles from the buffer, it has to be created with COPY_SRC usage
+pub fn download_particles(state: &StateData, buffer: &Buffer, count: usize) -> Result<Vec<Particle>, Error> {
+    state.read_buffer_as(buffer, count)
+}
+
+/// Hash the id into a stable, well distributed rgba color, 
+/// the render shader uses the same hash for the id and cell modes
+pub fn id_to_color(id: u64) -> [f32; 4] {
+    let mut x = (id ^ (id >> 32)) as u32;
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846ca68b);
+    x ^= x >> 16;
+
+    [
+        (x & 0xff) as f32 / 255f32,
+        ((x >> 8) & 0xff) as f32 / 255f32,
+        ((x >> 16) & 0xff) as f32 / 255f32,
+        1f32,
+    ]
+}
+
+/// Map the cursor position in the window onto the fluid coordinates, 
+/// mirrors the pixel mapping of the render shader
+pub fn cursor_to_fluid(
+    cursor: winit::dpi::PhysicalPosition<f64>, 
+    window_size: winit::dpi::PhysicalSize<u32>, 
+    texture_size: Size<u32>,
+) -> [f32; 2] {
+    // the texture is displayed with its first row at the bottom
+    let pixel_x = cursor.x / window_size.width as f64 * texture_size.width as f64;
+    let pixel_y = (1f64 - cursor.y / window_size.height as f64) * texture_size.height as f64;
+
+    let scale = [(texture_size.width / 30) as f32, (texture_size.height / 30) as f32];
+    let half = [(texture_size.width / 120) as f32 * 0.5f32, (texture_size.height / 120) as f32];
+
+    [pixel_x as f32 / scale[0] - half[0], pixel_y as f32 / scale[1] - half[1]]
+}
+
+#[cfg(feature = "imgui")]
 pub fn set_ui_size(window: &winit::window::Window, ui_context: &mut imgui::Context) {
     let hidpi_factor = window.scale_factor();
     ui_context.io_mut().font_global_scale = (1.0 / hidpi_factor) as f32;
@@ -221,13 +317,19 @@ pub mod window {
     }
 }
 
+/// Particle as laid out in the storage buffers (std430), vec2 fields are 8 byte aligned 
+/// so the u32 fields have to come in pairs before acceleration, any change has to be 
+/// mirrored in the Particle structs of the shaders
 #[repr(C)]
-#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Particle {
     position: [f32; 2],
     velocity: [f32; 2],
     mass: f32,
     density: f32,
+    alive: u32, // 0 once the particle left the domain through an open face
+    group: u32, // user tag, doesn't affect the simulation
+    acceleration: [f32; 2], // net acceleration of the last step
 }
 
 impl Particle {
@@ -237,6 +339,79 @@ impl Particle {
             velocity: [0f32, 0f32],
             mass: 10f32,
             density: 10f32,
+            alive: 1,
+            group: 0,
+            acceleration: [0f32, 0f32],
+        }
+    }
+
+    pub fn get_position(&self) -> [f32; 2] {
+        self.position
+    }
+
+    pub fn get_velocity(&self) -> [f32; 2] {
+        self.velocity
+    }
+
+    pub fn get_density(&self) -> f32 {
+        self.density
+    }
+
+    pub fn get_group(&self) -> u32 {
+        self.group
+    }
+
+    /// Get the net acceleration (pressure, viscosity, tension and gravity) acting 
+    /// on the particle during the last step
+    pub fn get_acceleration(&self) -> [f32; 2] {
+        self.acceleration
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+/// Describe what happens to a particle reaching a face of the domain
+pub enum BoundaryKind {
+    /// bounce the particle back into the domain
+    Reflect,
+    /// remove the particle from the simulation
+    Open,
+    /// move the particle to the opposite face
+    Periodic,
+}
+
+impl BoundaryKind {
+    pub fn get(&self) -> u32 {
+        match *self {
+            BoundaryKind::Reflect  => 0,
+            BoundaryKind::Open     => 1,
+            BoundaryKind::Periodic => 2,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+/// Describe how the time step of the simulation is chosen
+pub enum TimestepMode {
+    /// always use the given time step, check_time_step compares it to the CFL limit
+    Fixed(f32),
+    /// derive the time step from the maximal particle speed
+    Cfl,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+/// Integration scheme of the particle velocity and position
+pub enum Integrator {
+    /// kick the velocity by the full step, then drift the position with the new velocity
+    SemiImplicitEuler,
+    /// kick-drift-kick, the stored velocity is staggered by half a step
+    Leapfrog,
+}
+
+impl Integrator {
+    fn get(&self) -> u32 {
+        match self {
+            Integrator::SemiImplicitEuler => 0,
+            Integrator::Leapfrog          => 1,
         }
     }
 }
@@ -246,8 +421,93 @@ impl Particle {
 pub struct FluidInfo {
     time_step: f32,
     width: u32,
+    bounds_min: [f32; 2],
+    bounds_max: [f32; 2],
+    boundaries: [u32; 4], // left, right, bottom, top
+    density_epsilon: f32,
+    wall_velocities: [f32; 4], // left, right, bottom, top
+    surface_tension: f32,
+    cohesion_balance: f32,
+    max_cohesion: f32,
+    support_radius: f32,
+    integrator: u32,
+    gravity: [f32; 2],
+    last_time_step: f32, // zero until the first step
+    damping: f32,
+    viscosity: f32,
+    _padding: u32,
+}
+
+#[derive(Copy, Clone, Debug)]
+/// Timing information about the finished step, passed to the fluid observers
+pub struct StepTimings {
+    /// number of the step, starting at 0
+    pub step: u64,
+    /// simulated time of the step
+    pub time_step: f32,
+    /// wall time spent on the cpu side of the step
+    pub elapsed: time::Duration,
+}
+
+#[derive(Clone, Debug)]
+/// Distribution of the neighbor counts within the kernel support radius
+pub struct NeighborStats {
+    pub min: u32,
+    pub max: u32,
+    pub mean: f32,
+    /// number of particles with the given neighbor count, indexed by the count
+    pub histogram: Vec<u32>,
+}
+
+#[derive(Clone, Debug)]
+/// State of the active particles after a step
+pub struct Snapshot {
+    pub step: u64,
+    pub time_step: f32,
+    pub positions: Vec<[f32; 2]>,
+    pub kinetic_energy: f32,
+}
+
+impl Snapshot {
+    /// Get mean height of the particles, zero if there are none
+    pub fn mean_height(&self) -> f32 {
+        if self.positions.is_empty() {
+            return 0f32;
+        }
+
+        self.positions.iter().map(|[_, y]| y).sum::<f32>() / self.positions.len() as f32
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+/// Where the mass of the fluid went since its creation
+pub struct MassBalance {
+    /// mass of the particles at creation
+    pub initial: f32,
+    /// mass of the active particles inside the domain
+    pub total: f32,
+    /// mass added by emitters, zero as the fluid has none yet
+    pub added: f32,
+    /// mass removed deliberately through the open faces
+    pub removed: f32,
 }
 
+impl MassBalance {
+    /// Get the change of the total mass not explained by the emitters and open faces, 
+    /// mass of the particles which escaped the domain shows up here
+    pub fn imbalance(&self) -> f32 {
+        (self.total - self.initial) - (self.added - self.removed)
+    }
+
+    /// Check if the imbalance is larger than the rounding error of summing the masses
+    pub fn leaking(&self) -> bool {
+        self.imbalance().abs() > self.initial.abs() * 1e-4
+    }
+}
+
+/// Called at the end of every step, returning false halts the simulation
+pub type Observer = Box<dyn FnMut(&Fluid, &StepTimings) -> bool>;
+
 pub struct Fluid {
     computer: ComputePipeline,
     state: Rc<StateData>,
@@ -257,13 +517,30 @@ pub struct Fluid {
     particles_size: wgpu::BufferAddress,
 
     last_time: time::Instant,
+    step: u64,
+    gravity: [f32; 2],
+    gravity_ramp_steps: u64,
+    observers: Vec<Observer>,
+    is_halted: bool,
+    timestep_mode: TimestepMode,
+    is_cfl_warned: bool,
+    particle_size: f32,
+    rest_density: f32,
+    initial_mass: f32,
     info: FluidInfo,
     info_buffer: Buffer,
+    wall_stops: [f32; 4], // left, right, bottom, top
 
     surface: Buffer,
+    divergence: Buffer,
 }
 
 impl Fluid {
+    const CFL_FACTOR: f32 = 0.4;
+    const PARTICLE_RADIUS: f32 = 0.2;
+    const MAX_TIME_STEP: f32 = 0.1;
+    const SURFACE_SHIFT: f32 = 0.1;
+
     fn create_particles(size: Size<u32>) -> Vec<Particle> {
         let mut particles = vec![];
 
@@ -280,14 +557,83 @@ impl Fluid {
         particles
     }
 
-    pub fn new(state: &State, mut shader: Shader, size: Size<u32>) -> Self {
-        let start_time = time::Instant::now();
+    pub fn new(state: &State, shader: Shader, size: Size<u32>) -> Self {
         let particles = Self::create_particles(size);
+
+        Self::from_particles(state, shader, particles, size.width, 1f32)
+    }
+
+    /// Create fluid from the image, every pixel brighter than the threshold (0..=1, transparency 
+    /// darkens the pixel) becomes a particle, pixels are spacing apart and the bottom left 
+    /// corner of the image is placed at origin
+    pub fn from_image(
+        state: &State, 
+        shader: Shader, 
+        path: &str, 
+        threshold: f32, 
+        origin: [f32; 2], 
+        spacing: f32,
+    ) -> Result<Self, Error> {
+        let image = image::open(path)
+            .map_err(|err| Error::new(ErrorKind::InvalidData, err))?
+            .to_luma_alpha8();
+        let height = image.height();
+
+        let particles = image
+            .enumerate_pixels()
+            .filter(|(_, _, pixel)| {
+                let [luma, alpha] = pixel.0;
+                (luma as f32 / 255f32) * (alpha as f32 / 255f32) > threshold
+            })
+            .map(|(x, y, _)| Particle::new(
+                origin[0] + x as f32 * spacing,
+                origin[1] + (height - 1 - y) as f32 * spacing,
+            ))
+            .collect::<Vec<Particle>>();
+
+        if particles.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidData, "no pixel of the image is above the threshold"));
+        }
+
+        Ok(Self::from_particles(state, shader, particles, image.width(), spacing))
+    }
+
+    pub fn particle_count(&self) -> u32 {
+        (self.particles_size / std::mem::size_of::<Particle>() as u64) as u32
+    }
+
+    /// Create fluid from the particles, the shader is dispatched over rows of width particles,
+    /// particle_size is the initial spacing of the particles
+    pub fn from_particles(
+        state: &State, 
+        mut shader: Shader, 
+        particles: Vec<Particle>, 
+        width: u32, 
+        particle_size: f32,
+    ) -> Self {
+        let start_time = time::Instant::now();
+        let height = (particles.len() as u32).div_ceil(width);
+        let size = Size::new(width, height);
         let particles_size = std::mem::size_of_val(particles.as_slice()) as u64;
-        let _rest_density = 10f32;
+        let rest_density = 10f32;
         let info = FluidInfo{ 
             time_step: 0f32,
             width: size.width as u32,
+            bounds_min: [-0.1f32, 0f32],
+            bounds_max: [25f32, 20f32],
+            boundaries: [BoundaryKind::Reflect.get(); 4],
+            density_epsilon: 0.01f32,
+            wall_velocities: [0f32; 4],
+            surface_tension: 0f32,
+            cohesion_balance: 0.5f32,
+            max_cohesion: 1f32,
+            support_radius: 6f32,
+            integrator: Integrator::SemiImplicitEuler.get(),
+            gravity: [0f32, -0.1f32],
+            last_time_step: 0f32,
+            damping: 0f32,
+            viscosity: 0.7f32,
+            _padding: 0,
         };
 
         let particles_in = state.create_buffer_init(
@@ -295,9 +641,10 @@ impl Fluid {
             wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST, 
             Access::Both
         );
-        let particles_out = state.create_buffer(
-            particles_size, 
-            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        // seeded too, it holds the current state read back before the first step
+        let particles_out = state.create_buffer_init(
+            particles.as_slice(), 
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST,
             Access::Write
         );
         let info_buffer = state.create_buffer_init(
@@ -310,6 +657,11 @@ impl Fluid {
             wgpu::BufferUsages::STORAGE, 
             Access::Write
         );
+        let divergence = state.create_buffer(
+            particles.len() as u64 * 4, 
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC, 
+            Access::Write
+        );
 
         shader.add_entry(Box::new(particles_in.get_binding(None)));
         shader.add_entry(Box::new(particles_out.get_binding(None)));
@@ -319,9 +671,11 @@ impl Fluid {
         //     Access::Read
         // );
         shader.add_entry(Box::new(surface.get_binding(None)));
+        shader.add_entry(Box::new(divergence.get_binding(None)));
 
         let computer = ComputePipeline::new(state, shader, size, None);
         let state = state.get_state();
+        let initial_mass = particles.iter().map(|particle| particle.mass).sum();
         Fluid { 
             computer, 
             state, 
@@ -330,23 +684,731 @@ impl Fluid {
             particles_size, 
             info_buffer,
             info,
+            wall_stops: [0f32; 4],
+            rest_density,
+            step: 0,
+            gravity: info.gravity,
+            gravity_ramp_steps: 0,
+            observers: vec![],
+            is_halted: false,
+            timestep_mode: TimestepMode::Fixed(0.1f32),
+            is_cfl_warned: false,
+            particle_size,
+            initial_mass,
             last_time: start_time,
             surface: surface.get_binding(Some((Access::Read,))),
+            divergence: divergence.get_binding(Some((Access::Read,))),
+        }
+    }
+
+    /// Set the behavior of the domain faces, ordered as (left, right, bottom, top).
+    /// Periodic faces have to come in pairs, as the particle leaving one face 
+    /// enters through the opposite one
+    pub fn set_boundaries(&mut self, boundaries: [BoundaryKind; 4]) -> Result<(), Error> {
+        let is_periodic = |kind: BoundaryKind| kind == BoundaryKind::Periodic;
+
+        if is_periodic(boundaries[0]) != is_periodic(boundaries[1]) 
+            || is_periodic(boundaries[2]) != is_periodic(boundaries[3]) {
+            return Err(Error::new(ErrorKind::InvalidInput, "periodic boundary has to be set on both faces of an axis"));
+        }
+
+        self.info.boundaries = boundaries.map(|kind| kind.get());
+
+        Ok(())
+    }
+
+    /// Read the current state of particles back from the gpu, 
+    /// the initial particles until the first step finishes, panics if the device is lost
+    pub fn read_particles(&self) -> Vec<Particle> {
+        download_particles(&self.state, &self.particles_out, self.particle_count() as usize)
+            .expect("failed to read the particles back")
+    }
+
+    /// Overwrite the state of particles on the gpu, both buffers are written 
+    /// as the next step starts by copying the output into the input
+    fn write_particles(&self, particles: &[Particle]) {
+        self.state.queue.write_buffer(&self.particles_in, 0, bytemuck::cast_slice(particles));
+        self.state.queue.write_buffer(&self.particles_out, 0, bytemuck::cast_slice(particles));
+    }
+
+    /// Add velocity to all particles within the circle, with falloff the added 
+    /// velocity decreases linearly from the center to the edge of the circle
+    pub fn apply_impulse(&mut self, center: [f32; 2], radius: f32, delta_v: [f32; 2], falloff: bool) {
+        let mut particles = self.read_particles();
+
+        for particle in particles.iter_mut().filter(|particle| particle.alive != 0) {
+            let offset = [particle.position[0] - center[0], particle.position[1] - center[1]];
+            let distance = (offset[0] * offset[0] + offset[1] * offset[1]).sqrt();
+
+            if distance > radius {
+                continue;
+            }
+
+            let scale = if falloff { 1f32 - distance / radius } else { 1f32 };
+            particle.velocity[0] += delta_v[0] * scale;
+            particle.velocity[1] += delta_v[1] * scale;
+        }
+
+        self.write_particles(&particles);
+    }
+
+    /// Tag all particles within the circle with the group, returns the count of tagged particles,
+    /// can be called before the first step to mark the initial fluid
+    pub fn set_group(&mut self, center: [f32; 2], radius: f32, group: u32) -> usize {
+        let mut particles = self.read_particles();
+        let mut count = 0;
+
+        for particle in particles.iter_mut().filter(|particle| particle.alive != 0) {
+            let offset = [particle.position[0] - center[0], particle.position[1] - center[1]];
+            let distance = (offset[0] * offset[0] + offset[1] * offset[1]).sqrt();
+
+            if distance <= radius {
+                particle.group = group;
+                count += 1;
+            }
+        }
+
+        self.write_particles(&particles);
+        count
+    }
+
+    /// Get indices and particles of the group still in the simulation
+    pub fn group(&self, group: u32) -> impl Iterator<Item = (usize, Particle)> {
+        self.read_particles()
+            .into_iter()
+            .enumerate()
+            .filter(move |(_, particle)| particle.alive != 0 && particle.group == group)
+    }
+
+    /// Get index of the particle under the position, the nearest one if more overlap
+    pub fn pick(&self, position: [f32; 2]) -> Option<usize> {
+        self.read_particles()
+            .iter()
+            .enumerate()
+            .filter(|(_, particle)| particle.alive != 0)
+            .map(|(index, particle)| {
+                let offset = [particle.position[0] - position[0], particle.position[1] - position[1]];
+                (index, (offset[0] * offset[0] + offset[1] * offset[1]).sqrt())
+            })
+            .filter(|(_, distance)| *distance <= Self::PARTICLE_RADIUS)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(index, _)| index)
+    }
+
+    /// Get the divergence of the velocity field at every particle during the last step,
+    /// negative where the fluid compresses
+    pub fn velocity_divergence(&self) -> Vec<f32> {
+        self.state
+            .read_buffer_as(&self.divergence, self.particle_count() as usize)
+            .expect("failed to read the divergence back")
+    }
+
+    /// Get positions of the particles as interleaved floats (x, y), with padding 
+    /// as (x, y, 0, 1) so they can be uploaded as vec4
+    pub fn positions_flat(&self, padded: bool) -> Vec<f32> {
+        let mut positions = vec![];
+        self.positions_into(&mut positions, padded);
+
+        positions
+    }
+
+    /// Same as positions_flat, but reuses the allocation of the given vector
+    pub fn positions_into(&self, positions: &mut Vec<f32>, padded: bool) {
+        positions.clear();
+
+        for particle in self.read_particles().iter().filter(|particle| particle.alive != 0) {
+            positions.extend_from_slice(&particle.position);
+
+            if padded {
+                positions.extend_from_slice(&[0f32, 1f32]);
+            }
+        }
+    }
+
+    /// Get the net acceleration of every particle during the last step
+    pub fn accelerations(&self) -> impl Iterator<Item = [f32; 2]> {
+        self.read_particles()
+            .into_iter()
+            .map(|particle| particle.acceleration)
+    }
+
+    /// Get speeds of all particles still in the simulation
+    fn speeds(&self) -> Vec<f32> {
+        self.read_particles()
+            .iter()
+            .filter(|particle| particle.alive != 0)
+            .map(|particle| {
+                let [x, y] = particle.velocity;
+                (x * x + y * y).sqrt()
+            })
+            .collect()
+    }
+
+    /// Count the particles by their speed into `bins` equally sized bins 
+    /// spanning from zero to the maximal speed
+    pub fn velocity_histogram(&self, bins: usize) -> Vec<u32> {
+        histogram(&self.speeds(), bins)
+    }
+
+    /// Get speeds at given percentiles (in range 0..=1) using the nearest rank, 
+    /// returns empty vector if there are no particles
+    pub fn speed_percentiles(&self, percentiles: &[f32]) -> Vec<f32> {
+        nearest_rank_percentiles(&self.speeds(), percentiles)
+    }
+
+    /// Get the offset from ri to the closest image of rj, taking periodic faces into 
+    /// account, same as periodic_image of the fluid shader
+    fn periodic_offset(&self, ri: [f32; 2], rj: [f32; 2]) -> [f32; 2] {
+        let periodic = BoundaryKind::Periodic.get();
+        let is_periodic = [self.info.boundaries[0] == periodic, self.info.boundaries[2] == periodic];
+        let mut offset = [rj[0] - ri[0], rj[1] - ri[1]];
+
+        for axis in 0..2 {
+            if !is_periodic[axis] {
+                continue;
+            }
+
+            let extent = self.info.bounds_max[axis] - self.info.bounds_min[axis];
+
+            if offset[axis] > extent * 0.5f32 {
+                offset[axis] -= extent;
+            } else if offset[axis] < -extent * 0.5f32 {
+                offset[axis] += extent;
+            }
+        }
+
+        offset
+    }
+
+    /// Count the neighbors of every active particle within the support radius, 
+    /// a wide or bimodal distribution hints at a badly chosen radius or clustering
+    pub fn neighbor_count_stats(&self) -> Option<NeighborStats> {
+        let positions = self.read_particles()
+            .into_iter()
+            .filter(|particle| particle.alive != 0)
+            .map(|particle| particle.position)
+            .collect::<Vec<[f32; 2]>>();
+
+        if positions.is_empty() {
+            return None;
+        }
+
+        let radius = self.info.support_radius;
+        let counts = positions
+            .iter()
+            .enumerate()
+            .map(|(i, position)| {
+                positions
+                    .iter()
+                    .enumerate()
+                    .filter(|(j, neighbor)| {
+                        let [dx, dy] = self.periodic_offset(*position, **neighbor);
+                        *j != i && dx * dx + dy * dy <= radius * radius
+                    })
+                    .count() as u32
+            })
+            .collect::<Vec<u32>>();
+
+        let min = *counts.iter().min().unwrap();
+        let max = *counts.iter().max().unwrap();
+        let mean = counts.iter().sum::<u32>() as f32 / counts.len() as f32;
+
+        let mut histogram = vec![0u32; max as usize + 1];
+        for count in counts {
+            histogram[count as usize] += 1;
+        }
+
+        Some(NeighborStats { min, max, mean, histogram })
+    }
+
+    /// Get the total kinetic energy of the particles, the staggered leapfrog velocity 
+    /// gets the rest of the last kick first, so it matches the positions
+    pub fn kinetic_energy(&self) -> f32 {
+        self.kinetic_energy_of(&self.read_particles())
+    }
+
+    fn kinetic_energy_of(&self, particles: &[Particle]) -> f32 {
+        let half_step = if self.info.integrator == Integrator::Leapfrog.get() {
+            0.5f32 * self.info.last_time_step
+        } else {
+            0f32
+        };
+
+        particles
+            .iter()
+            .filter(|particle| particle.alive != 0)
+            .map(|particle| {
+                let [ax, ay] = particle.acceleration;
+                let [x, y] = particle.velocity;
+                let [x, y] = [x + ax * half_step, y + ay * half_step];
+                0.5f32 * particle.mass * (x * x + y * y)
+            })
+            .sum()
+    }
+
+    /// Run n steps and snapshot the particles after each one, 
+    /// stops early if an observer halts the simulation
+    pub fn run(&mut self, n: usize) -> Vec<Snapshot> {
+        let mut snapshots = Vec::with_capacity(n);
+
+        for _ in 0..n {
+            if self.is_halted {
+                break;
+            }
+
+            self.update();
+
+            let particles = self.read_particles();
+
+            snapshots.push(Snapshot {
+                step: self.step - 1,
+                time_step: self.info.time_step,
+                positions: particles
+                    .iter()
+                    .filter(|particle| particle.alive != 0)
+                    .map(|particle| particle.position)
+                    .collect(),
+                kinetic_energy: self.kinetic_energy_of(&particles),
+            });
+        }
+
+        snapshots
+    }
+
+    /// Get the smallest axis aligned box (min, max) containing all particles, 
+    /// None if there are no particles left
+    pub fn aabb(&self) -> Option<([f32; 2], [f32; 2])> {
+        self.read_particles()
+            .iter()
+            .filter(|particle| particle.alive != 0)
+            .map(|particle| particle.position)
+            .fold(None, |bounds, position| {
+                let (min, max) = bounds.unwrap_or((position, position));
+
+                Some((
+                    [min[0].min(position[0]), min[1].min(position[1])],
+                    [max[0].max(position[0]), max[1].max(position[1])],
+                ))
+            })
+    }
+
+    /// Get the mass of all particles still in the simulation
+    pub fn total_mass(&self) -> f32 {
+        self.read_particles()
+            .iter()
+            .filter(|particle| particle.alive != 0)
+            .map(|particle| particle.mass)
+            .sum()
+    }
+
+    /// Account for the mass of the fluid, in a closed domain the total mass stays at the 
+    /// initial one, open faces remove it and particles escaping the walls leak it
+    pub fn mass_balance(&self) -> MassBalance {
+        let mut total = 0f32;
+        let mut removed = 0f32;
+
+        let [min_x, min_y] = self.info.bounds_min;
+        let [max_x, max_y] = self.info.bounds_max;
+        for particle in self.read_particles() {
+            let [x, y] = particle.position;
+
+            if particle.alive == 0 {
+                removed += particle.mass;
+            } else if x >= min_x && x <= max_x && y >= min_y && y <= max_y {
+                total += particle.mass;
+            }
+        }
+
+        MassBalance { 
+            initial: self.initial_mass, 
+            total, 
+            added: 0f32,
+            removed, 
+        }
+    }
+
+    /// Estimate the volume occupied by the fluid as the sum of particle volumes at rest density
+    pub fn estimated_volume(&self) -> f32 {
+        self.read_particles()
+            .iter()
+            .filter(|particle| particle.alive != 0)
+            .map(|particle| particle.mass / self.rest_density)
+            .sum()
+    }
+
+    /// Estimate the surface of the fluid, in 2D its perimeter, as the sum of the representative 
+    /// size (m / rho)^(1/2) of the surface particles, a particle is on the surface when the 
+    /// centroid of its neighbors is shifted away from it by a fraction of the support radius
+    pub fn surface_area_estimate(&self) -> f32 {
+        let particles = self.read_particles()
+            .into_iter()
+            .filter(|particle| particle.alive != 0)
+            .collect::<Vec<Particle>>();
+        let radius = self.info.support_radius;
+
+        particles
+            .iter()
+            .filter(|particle| {
+                let [x, y] = particle.position;
+                let mut offset = [0f32; 2];
+                let mut count = 0;
+
+                for neighbor in particles.iter() {
+                    let [nx, ny] = neighbor.position;
+                    if (nx - x).powi(2) + (ny - y).powi(2) <= radius * radius {
+                        offset[0] += nx - x;
+                        offset[1] += ny - y;
+                        count += 1;
+                    }
+                }
+
+                // the particle itself is always counted
+                let shift = (offset[0].powi(2) + offset[1].powi(2)).sqrt() / count as f32;
+                count == 1 || shift > Self::SURFACE_SHIFT * radius
+            })
+            .map(|particle| (particle.mass / particle.density.max(self.info.density_epsilon)).sqrt())
+            .sum()
+    }
+
+    /// Set the velocity of the domain faces along their normal axis, ordered as 
+    /// (left, right, bottom, top), the faces move every step and push reflected particles
+    /// until they reach their stop position, where they halt. Faces with zero velocity 
+    /// ignore the stop, the stops of moving faces have to be ahead of them in the direction 
+    /// of motion and the faces of each axis have to stay apart the whole way
+    pub fn set_wall_velocities(&mut self, velocities: [f32; 4], stops: [f32; 4]) -> Result<(), Error> {
+        let [min_x, min_y] = self.info.bounds_min;
+        let [max_x, max_y] = self.info.bounds_max;
+        let faces = [min_x, max_x, min_y, max_y];
+
+        for (face, (&velocity, (&stop, &position))) in velocities.iter().zip(stops.iter().zip(faces.iter())).enumerate() {
+            let is_ahead = (velocity > 0f32 && stop > position) || (velocity < 0f32 && stop < position);
+
+            if velocity != 0f32 && !is_ahead {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput, 
+                    format!("stop {} of face {} isn't ahead of it in the direction of motion", stop, face)
+                ));
+            }
+        }
+
+        // faces only move towards their stops, so they can't cross if the spans they sweep don't overlap
+        let end = |face: usize| if velocities[face] != 0f32 { stops[face] } else { faces[face] };
+        let is_crossing = |min: usize, max: usize| faces[min].max(end(min)) >= faces[max].min(end(max));
+
+        if is_crossing(0, 1) || is_crossing(2, 3) {
+            return Err(Error::new(ErrorKind::InvalidInput, "moving walls would cross the opposite face"));
+        }
+
+        self.info.wall_velocities = velocities;
+        self.wall_stops = stops;
+
+        Ok(())
+    }
+
+    /// Move the domain faces by their velocities, halting the faces reaching their stops
+    fn move_walls(&mut self, time_step: f32) {
+        let [min_x, min_y] = self.info.bounds_min;
+        let [max_x, max_y] = self.info.bounds_max;
+        let mut faces = [min_x, max_x, min_y, max_y];
+
+        for (face, position) in faces.iter_mut().enumerate() {
+            let velocity = self.info.wall_velocities[face];
+            if velocity == 0f32 {
+                continue;
+            }
+
+            let stop = self.wall_stops[face];
+            let moved = *position + velocity * time_step;
+
+            if (velocity > 0f32 && moved >= stop) || (velocity < 0f32 && moved <= stop) {
+                *position = stop;
+                self.info.wall_velocities[face] = 0f32;
+            } else {
+                *position = moved;
+            }
+        }
+
+        self.info.bounds_min = [faces[0], faces[2]];
+        self.info.bounds_max = [faces[1], faces[3]];
+    }
+
+    /// Get the current domain bounds (min, max)
+    pub fn bounds(&self) -> ([f32; 2], [f32; 2]) {
+        (self.info.bounds_min, self.info.bounds_max)
+    }
+
+    /// Set the surface tension coefficient, balance weights cohesion against the 
+    /// curvature term (0 only curvature, 1 only cohesion), and the cohesion between 
+    /// any two particles is clamped to max_cohesion to keep strong tension stable
+    pub fn set_surface_tension(&mut self, surface_tension: f32, cohesion_balance: f32, max_cohesion: f32) {
+        self.info.surface_tension = surface_tension;
+        self.info.cohesion_balance = cohesion_balance.clamp(0f32, 1f32);
+        self.info.max_cohesion = max_cohesion;
+    }
+
+    /// Set the viscosity coefficient scaling the viscous force between neighbors
+    pub fn set_viscosity(&mut self, viscosity: f32) {
+        self.info.viscosity = viscosity.max(0f32);
+    }
+
+    pub fn viscosity(&self) -> f32 {
+        self.info.viscosity
+    }
+
+    pub fn surface_tension(&self) -> f32 {
+        self.info.surface_tension
+    }
+
+    /// Set the ambient damping, every step scales the velocities by (1 - damping * dt) 
+    /// letting the fluid come to rest, zero disables it
+    pub fn set_damping(&mut self, damping: f32) {
+        self.info.damping = damping.max(0f32);
+    }
+
+    /// Set the ratio of the kernel support radius to the particle size, larger ratio 
+    /// takes more neighbors into account resulting in smoother but costlier simulation, 
+    /// the ratio has to be positive
+    pub fn set_smoothing_ratio(&mut self, smoothing_ratio: f32) -> Result<(), Error> {
+        if smoothing_ratio.is_nan() || smoothing_ratio <= 0f32 {
+            return Err(Error::new(ErrorKind::InvalidInput, "smoothing ratio has to be positive"));
+        }
+
+        self.info.support_radius = smoothing_ratio * self.particle_size;
+        Ok(())
+    }
+
+    /// Get the kernel support radius
+    pub fn support_radius(&self) -> f32 {
+        self.info.support_radius
+    }
+
+    pub fn set_gravity(&mut self, gravity: [f32; 2]) {
+        self.gravity = gravity;
+    }
+
+    /// Scale gravity linearly from zero to full over the first steps, so a poorly 
+    /// packed fluid eases into motion, zero steps disables the ramp
+    pub fn set_gravity_ramp(&mut self, steps: u64) {
+        self.gravity_ramp_steps = steps;
+    }
+
+    /// Set the smallest density used as a divisor, neighbors with lower density are ignored
+    /// in force calculations, so nearly isolated particles don't produce runaway forces
+    pub fn set_density_epsilon(&mut self, epsilon: f32) {
+        self.info.density_epsilon = epsilon;
+    }
+
+    /// Register a callback invoked at the end of every step
+    pub fn add_observer(&mut self, observer: Observer) {
+        self.observers.push(observer);
+    }
+
+    /// Check if some observer halted the simulation
+    pub fn is_halted(&self) -> bool {
+        self.is_halted
+    }
+
+    /// Continue the simulation halted by an observer
+    pub fn resume(&mut self) {
+        self.is_halted = false;
+    }
+
+    pub fn set_timestep_mode(&mut self, mode: TimestepMode) {
+        self.timestep_mode = mode;
+        self.is_cfl_warned = false;
+    }
+
+    /// Switching to leapfrog restarts the staggering, the first kick is then only half a step
+    pub fn set_integrator(&mut self, integrator: Integrator) {
+        if integrator.get() != self.info.integrator {
+            self.info.last_time_step = 0f32;
+        }
+
+        self.info.integrator = integrator.get();
+    }
+
+    /// Get the largest time step for which no particle travels further than 
+    /// a fraction of its size, reads the particles back so it stalls the gpu
+    pub fn cfl_time_step(&self) -> f32 {
+        let max_speed = self.speeds().into_iter().fold(0f32, f32::max);
+
+        if max_speed > 0f32 {
+            (Self::CFL_FACTOR * self.particle_size / max_speed).min(Self::MAX_TIME_STEP)
+        } else {
+            Self::MAX_TIME_STEP
         }
     }
 
+    /// Check the fixed time step against the CFL limit, returns false and warns 
+    /// (only the first time) when it's exceeded, always true in the Cfl mode
+    pub fn check_time_step(&mut self) -> bool {
+        let time_step = match self.timestep_mode {
+            TimestepMode::Fixed(time_step) => time_step,
+            TimestepMode::Cfl => return true,
+        };
+
+        let cfl_time_step = self.cfl_time_step();
+        if time_step <= cfl_time_step {
+            return true;
+        }
+
+        if !self.is_cfl_warned {
+            tracing::warn!("fixed time step {} exceeds the CFL limit {}", time_step, cfl_time_step);
+            self.is_cfl_warned = true;
+        }
+
+        false
+    }
+
     /// Update the state of fluid (run the shader)
     pub fn update(&mut self) {
+        if self.is_halted {
+            return;
+        }
+
+        let start_time = time::Instant::now();
         let mut encoder = self.computer.start_execute();
         encoder.copy_buffer_to_buffer(&self.particles_out, 0, &self.particles_in, 0, self.particles_size);
 
         let _time_step = self.last_time.elapsed().as_secs_f32();
-        let time_step = 0.1f32;
+        let time_step = match self.timestep_mode {
+            TimestepMode::Fixed(time_step) => time_step,
+            TimestepMode::Cfl => self.cfl_time_step(),
+        };
         let instance  = time::Instant::now();
         self.info.time_step = time_step;
+
+        let gravity_scale = if self.step < self.gravity_ramp_steps {
+            (self.step + 1) as f32 / self.gravity_ramp_steps as f32
+        } else {
+            1f32
+        };
+        self.info.gravity = self.gravity.map(|g| g * gravity_scale);
+
+        self.move_walls(time_step);
+
         self.state.queue.write_buffer(&self.info_buffer, 0, bytemuck::cast_slice(&[self.info]));
+        self.info.last_time_step = time_step;
 
         self.last_time = instance;
         self.state.queue.submit(std::iter::once(encoder.finish()));
+
+        let timings = StepTimings {
+            step: self.step,
+            time_step,
+            elapsed: start_time.elapsed(),
+        };
+        self.step += 1;
+
+        // take the observers out, so they can look at the fluid
+        let mut observers = std::mem::take(&mut self.observers);
+        for observer in observers.iter_mut() {
+            if !observer(self, &timings) {
+                self.is_halted = true;
+            }
+        }
+        self.observers = observers;
+    }
+}
+
+/// Count the non negative values into `bins` equally sized bins spanning from zero to the maximum
+fn histogram(values: &[f32], bins: usize) -> Vec<u32> {
+    let mut histogram = vec![0u32; bins];
+    if bins == 0 {
+        return histogram;
+    }
+
+    let max_value = values.iter().cloned().fold(0f32, f32::max);
+
+    for value in values {
+        let bin = if max_value > 0f32 {
+            ((value / max_value) * bins as f32) as usize
+        } else {
+            0
+        };
+
+        histogram[bin.min(bins - 1)] += 1;
+    }
+
+    histogram
+}
+
+/// Get values at given percentiles (in range 0..=1) using the nearest rank, 
+/// returns empty vector if there are no values
+fn nearest_rank_percentiles(values: &[f32], percentiles: &[f32]) -> Vec<f32> {
+    if values.is_empty() {
+        return vec![];
+    }
+
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let last = sorted.len() - 1;
+
+    percentiles
+        .iter()
+        .map(|percentile| {
+            let rank = (percentile.clamp(0f32, 1f32) * last as f32).round() as usize;
+            sorted[rank]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // deterministic spread of speeds, with repeats and zeros
+    fn speeds() -> Vec<f32> {
+        (0..1000u32).map(|i| ((i * 7919) % 113) as f32 * 0.25).collect()
+    }
+
+    #[test]
+    fn histogram_sums_to_count() {
+        let speeds = speeds();
+
+        for bins in [1, 2, 7, 64] {
+            let histogram = histogram(&speeds, bins);
+            assert_eq!(histogram.len(), bins);
+            assert_eq!(histogram.iter().sum::<u32>() as usize, speeds.len());
+        }
+
+        // the maximum falls into the last bin instead of past it
+        assert_eq!(histogram(&[0f32, 1f32], 2), vec![1, 1]);
+        assert_eq!(histogram(&[0f32, 0f32], 3), vec![2, 0, 0]);
+    }
+
+    #[test]
+    fn percentiles_match_sorted_reference() {
+        let speeds = speeds();
+        let mut sorted = speeds.clone();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+
+        let percentiles = [0f32, 0.1, 0.25, 0.5, 0.9, 0.99, 1f32];
+        let expected = percentiles
+            .iter()
+            .map(|percentile| sorted[(percentile * (sorted.len() - 1) as f32).round() as usize])
+            .collect::<Vec<f32>>();
+
+        assert_eq!(nearest_rank_percentiles(&speeds, &percentiles), expected);
+        assert_eq!(nearest_rank_percentiles(&speeds, &[0f32, 1f32]), vec![sorted[0], sorted[sorted.len() - 1]]);
+    }
+
+    #[test]
+    fn empty_input() {
+        assert_eq!(histogram(&[], 4), vec![0; 4]);
+        assert!(histogram(&speeds(), 0).is_empty());
+        assert!(nearest_rank_percentiles(&[], &[0.5]).is_empty());
+    }
+
+    #[test]
+    fn mass_imbalance() {
+        // draining through the open faces is accounted for
+        let drained = MassBalance { initial: 100f32, total: 70f32, added: 0f32, removed: 30f32 };
+        assert_eq!(drained.imbalance(), 0f32);
+        assert!(!drained.leaking());
+
+        let leaked = MassBalance { removed: 20f32, ..drained };
+        assert_eq!(leaked.imbalance(), -10f32);
+        assert!(leaked.leaking());
     }
 }
@@ -0,0 +1,81 @@
+use crate::backend::*;
+
+
+/// Exclusive prefix sum of u32 buffer on the gpu, arrays longer than a single 
+/// block are scanned per block and the block totals are scanned recursively
+pub struct PrefixSum {
+    scans: Vec<ComputePipeline>,
+    adds: Vec<ComputePipeline>,
+
+    // keep the block totals and counts of every level alive
+    _sums: Vec<Buffer>,
+    _infos: Vec<Buffer>,
+}
+
+impl PrefixSum {
+    const BLOCK_SIZE: u32 = 256;
+
+    /// Create new prefix sum over the first count elements of data, 
+    /// the shader is the prefix sum shader, its entry point doesn't matter
+    pub fn new(state: &State, shader: Shader, data: &Buffer, count: u32) -> Self {
+        let mut scans = vec![];
+        let mut adds = vec![];
+        let mut sums = vec![];
+        let mut infos = vec![];
+
+        let mut level = data.get_binding(Some((Access::Both, )));
+        let mut count = count.max(1);
+
+        loop {
+            let blocks = count.div_ceil(Self::BLOCK_SIZE);
+            let block_sums = state.create_buffer(
+                blocks as u64 * 4, 
+                wgpu::BufferUsages::STORAGE, 
+                Access::Both
+            );
+            let info = state.create_buffer_init(
+                &[count], 
+                wgpu::BufferUsages::STORAGE, 
+                Access::Read
+            );
+
+            for (entry, pipelines) in [("scan", &mut scans), ("add", &mut adds)] {
+                let mut shader = shader.new_from(entry, Visibility::COMPUTE);
+                shader.add_entry(Box::new(level.get_binding(None)));
+                shader.add_entry(Box::new(block_sums.get_binding(None)));
+                shader.add_entry(Box::new(info.get_binding(None)));
+
+                pipelines.push(ComputePipeline::new_1d(state, shader, count, Some(Self::BLOCK_SIZE)));
+            }
+
+            level = block_sums.get_binding(None);
+            sums.push(block_sums);
+            infos.push(info);
+
+            if blocks == 1 {
+                break;
+            }
+            count = blocks;
+        }
+
+        PrefixSum { 
+            scans, 
+            adds, 
+            _sums: sums, 
+            _infos: infos,
+        }
+    }
+
+    /// Replace the data with its exclusive prefix sum
+    pub fn execute(&mut self) {
+        for scan in self.scans.iter_mut() {
+            scan.execute();
+        }
+
+        // the last level fits a single block, so it has nothing to add
+        let levels = self.adds.len();
+        for add in self.adds.iter_mut().take(levels - 1).rev() {
+            add.execute();
+        }
+    }
+}
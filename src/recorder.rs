@@ -0,0 +1,75 @@
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::backend::RenderPipeline;
+
+
+/// Write rendered frames as numbered png images into a directory and optionally 
+/// assemble them into a video with ffmpeg
+pub struct VideoRecorder {
+    directory: PathBuf,
+    fps: u32,
+    frame: u32,
+}
+
+impl VideoRecorder {
+    /// Create new recorder writing into the directory (created if missing), 
+    /// the fps is used only for the assembled video, the simulation should run 
+    /// with a fixed time step for the video to be frame accurate
+    pub fn new(directory: impl AsRef<Path>, fps: u32) -> Result<Self, Error> {
+        let directory = directory.as_ref().to_path_buf();
+        fs::create_dir_all(&directory)?;
+
+        Ok(VideoRecorder { 
+            directory, 
+            fps, 
+            frame: 0,
+        })
+    }
+
+    fn frame_path(&self, frame: u32) -> PathBuf {
+        self.directory.join(format!("frame_{:05}.png", frame))
+    }
+
+    /// Save the current render texture of the pipeline as the next frame
+    pub fn record(&mut self, pipeline: &RenderPipeline) -> Result<(), Error> {
+        pipeline
+            .capture_frame()?
+            .save(self.frame_path(self.frame))
+            .map_err(Error::other)?;
+
+        self.frame += 1;
+
+        Ok(())
+    }
+
+    /// Get the number of recorded frames
+    pub fn frames(&self) -> u32 {
+        self.frame
+    }
+
+    /// Assemble the recorded frames into mp4 video, returns the path to the video or None 
+    /// if ffmpeg isn't available, in which case only the frames are left in the directory
+    pub fn finish(&self) -> Result<Option<PathBuf>, Error> {
+        let video = self.directory.join("video.mp4");
+        let output = Command::new("ffmpeg")
+            .arg("-y")
+            .arg("-framerate").arg(self.fps.to_string())
+            .arg("-i").arg(self.directory.join("frame_%05d.png"))
+            .args(["-pix_fmt", "yuv420p"])
+            .arg(&video)
+            .output();
+
+        match output {
+            Ok(output) if output.status.success() => Ok(Some(video)),
+            Ok(output) => Err(Error::other(String::from_utf8_lossy(&output.stderr).into_owned())),
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                tracing::warn!("ffmpeg not found, leaving only the frames in {:?}", self.directory);
+                Ok(None)
+            },
+            Err(err) => Err(err),
+        }
+    }
+}
@@ -0,0 +1,502 @@
+//! Checks running on the gpu, they need an adapter and a display so they're ignored
+//! by default, run them with `cargo test --test gpu -- --ignored`. Winit allows only
+//! one event loop per process, so all the checks share one window in a single test
+
+use nikola::*;
+
+fn init_window() -> (winit::event_loop::EventLoop<()>, winit::window::Window) {
+    let mut builder = winit::event_loop::EventLoopBuilder::new();
+    // the test harness doesn't run on the main thread
+    #[cfg(target_os = "linux")]
+    winit::platform::unix::EventLoopBuilderExtUnix::with_any_thread(&mut builder, true);
+    #[cfg(target_os = "windows")]
+    winit::platform::windows::EventLoopBuilderExtWindows::with_any_thread(&mut builder, true);
+
+    let event_loop = builder.build();
+    let window = winit::window::WindowBuilder::new()
+        .with_inner_size(winit::dpi::PhysicalSize::new(256u32, 256u32))
+        .with_visible(false)
+        .build(&event_loop)
+        .unwrap();
+
+    (event_loop, window)
+}
+
+const FLUID_SHADER: &str = "./res/shaders/fluid_shader.wgsl";
+const SCREEN_SHADER: &str = "./res/shaders/screen_shader.wgsl";
+
+/// Write the test shader to the temp dir, shaders are loaded from 'static paths
+fn write_shader(name: &str, source: &str) -> &'static str {
+    let path = std::env::temp_dir().join(format!("nikola_{}.wgsl", name));
+    std::fs::write(&path, source).unwrap();
+
+    Box::leak(path.to_string_lossy().into_owned().into_boxed_str())
+}
+
+fn isolated_particle_pressure(state: &State) {
+    let shader = Shader::new(state, FLUID_SHADER, "main", Visibility::COMPUTE);
+    let mut fluid = Fluid::new(state, shader, Size::new(1, 1));
+    fluid.update();
+
+    // no neighbors means zero density, only the gravity is left instead of a spike (or NaN)
+    let acceleration = fluid.read_particles()[0].get_acceleration();
+    assert!(acceleration.iter().all(|value| value.is_finite()));
+    assert!(acceleration[0].abs() < 1e-6);
+    assert!((acceleration[1] + 0.1f32).abs() < 1e-6);
+}
+
+fn isolated_particle_normal(state: &State) {
+    // run the fluid shader's normalization on the zero color field gradient of a lonely particle
+    let source = std::fs::read_to_string(FLUID_SHADER).unwrap();
+    let epsilon = source.lines().find(|line| line.starts_with("let EPSILON")).unwrap();
+    let start = source.find("fn safe_normalize").unwrap();
+    let end = start + source[start..].find("\n}\n").unwrap() + 3;
+    let source = format!(
+        "{}\n{}\n@group(0) @binding(0) var<storage, read> input: array<vec2<f32>>;\n\
+         @group(0) @binding(1) var<storage, read_write> output: array<vec2<f32>>;\n\
+         @compute @workgroup_size(1)\n\
+         fn main(@builtin(global_invocation_id) id: vec3<u32>) {{ output[id.x] = safe_normalize(input[id.x]); }}\n",
+        epsilon, &source[start..end],
+    );
+    let path = write_shader("safe_normalize", &source);
+
+    let input = [[0f32, 0f32], [3f32, 4f32]];
+    let size = std::mem::size_of_val(&input) as u64;
+    let output = state.create_buffer(size, wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC, Access::Write);
+
+    let mut shader = Shader::new(state, path, "main", Visibility::COMPUTE);
+    shader.add_entry(Box::new(state.create_buffer_init(&input, wgpu::BufferUsages::STORAGE, Access::Read)));
+    shader.add_entry(Box::new(output.get_binding(None)));
+    let mut pipeline = ComputePipeline::new(state, shader, Size::new(2, 1), Some(Size::new(1, 1)));
+    pipeline.execute();
+
+    let normals: Vec<f32> = bytemuck::cast_slice(&state.read_buffer(&output, size).unwrap()).to_vec();
+    assert_eq!(&normals[..2], &[0f32, 0f32]);
+    assert!((normals[2] - 0.6).abs() < 1e-6 && (normals[3] - 0.8).abs() < 1e-6);
+}
+
+fn leapfrog_energy_drift(state: &State) {
+    let drift = |integrator| {
+        // a lone particle falls freely, so the total energy should stay put
+        let shader = Shader::new(state, FLUID_SHADER, "main", Visibility::COMPUTE);
+        let mut fluid = Fluid::from_particles(state, shader, vec![Particle::new(10f32, 18f32)], 1, 1f32);
+        fluid.set_integrator(integrator);
+
+        // Particle::new has mass 10, the default gravity is 0.1 downwards
+        let energy = |fluid: &Fluid| fluid.kinetic_energy() + 10f32 * 0.1f32 * fluid.positions_flat(false)[1];
+        let initial = energy(&fluid);
+        for _ in 0..150 {
+            fluid.update();
+        }
+
+        (energy(&fluid) - initial).abs()
+    };
+
+    let euler = drift(Integrator::SemiImplicitEuler);
+    let leapfrog = drift(Integrator::Leapfrog);
+    assert!(leapfrog < euler, "leapfrog drift {} isn't below euler drift {}", leapfrog, euler);
+}
+
+fn neighbors_grow_with_smoothing_ratio(state: &State) {
+    let shader = Shader::new(state, FLUID_SHADER, "main", Visibility::COMPUTE);
+    let mut fluid = Fluid::new(state, shader, Size::new(10, 10));
+
+    let mut last_mean = 0f32;
+    for ratio in [1.5f32, 3f32, 6f32] {
+        fluid.set_smoothing_ratio(ratio).unwrap();
+        let mean = fluid.neighbor_count_stats().unwrap().mean;
+        assert!(mean > last_mean, "ratio {} has {} neighbors on average, not more than {}", ratio, mean, last_mean);
+        last_mean = mean;
+    }
+
+    assert!(fluid.set_smoothing_ratio(0f32).is_err());
+    assert!(fluid.set_smoothing_ratio(f32::NAN).is_err());
+}
+
+fn resize_texture_compute_pipeline(state: &State) {
+    // every dispatch writes the pixel coordinates and counts the dispatches in blue
+    let path = write_shader("ping_pong", "\
+        @group(0) @binding(0) var input: texture_2d<f32>;\n\
+        @group(0) @binding(1) var output: texture_storage_2d<rgba8unorm, write>;\n\
+        @compute @workgroup_size(8, 8)\n\
+        fn main(@builtin(global_invocation_id) id: vec3<u32>) {\n\
+            let size = textureDimensions(output);\n\
+            if (i32(id.x) >= size.x || i32(id.y) >= size.y) { return; }\n\
+            let count = textureLoad(input, vec2<i32>(id.xy), 0).b + 1f / 255f;\n\
+            textureStore(output, vec2<i32>(id.xy), vec4(f32(id.x) / 255f, f32(id.y) / 255f, count, 1f));\n\
+        }\n");
+
+    let mut shader = Shader::new(state, path, "main", Visibility::COMPUTE);
+    // the default features don't allow read_write storage of rgba8unorm
+    assert_eq!(shader.create_storage_texture(Size::new(8, 8), FORMAT), StorageTextureMode::PingPong);
+    let mut pipeline = ComputePipeline::new(state, shader, Size::new(8, 8), None);
+    pipeline.execute();
+
+    // the contents are dropped on resize, so the count starts over
+    let size = Size::new(21, 13);
+    pipeline.resize(size);
+    pipeline.execute();
+    pipeline.execute();
+
+    // reading the whole region fails if the texture kept its old size
+    let texture = pipeline.get_owned_texture(0).unwrap();
+    let pixels = state.read_texture(&texture, wgpu::Origin3d::ZERO, size).unwrap();
+    for (index, pixel) in pixels.chunks(4).enumerate() {
+        let (x, y) = (index as u32 % size.width, index as u32 / size.width);
+        assert_eq!(pixel, [x as u8, y as u8, 2, 255], "pixel ({}, {})", x, y);
+    }
+}
+
+fn dropped_blob_falls(state: &State) {
+    let particles = (0..25)
+        .map(|index| Particle::new(5f32 + (index % 5) as f32, 12f32 + (index / 5) as f32))
+        .collect();
+    let shader = Shader::new(state, FLUID_SHADER, "main", Visibility::COMPUTE);
+    let mut fluid = Fluid::from_particles(state, shader, particles, 5, 1f32);
+
+    // 40 steps aren't enough to reach the floor, so nothing stops the fall
+    let heights = fluid.run(40)
+        .iter()
+        .map(|snapshot| snapshot.mean_height())
+        .collect::<Vec<f32>>();
+    assert_eq!(heights.len(), 40);
+    assert!(heights[0] < 14f32);
+    for (step, pair) in heights.windows(2).enumerate() {
+        assert!(pair[1] < pair[0], "mean height rose from {} to {} after step {}", pair[0], pair[1], step + 1);
+    }
+}
+
+fn prefix_sum_matches_cpu(state: &State) {
+    // xorshift, so the arrays are random but reproducible
+    let mut seed = 0x2545f491u32;
+    let mut random = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        seed
+    };
+
+    for count in [1u32, 255, 256, 257, 65_539, 3_000_000] {
+        let values = (0..count).map(|_| random() % 1000).collect::<Vec<u32>>();
+        let data = state.create_buffer_init(
+            &values, 
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC, 
+            Access::Both
+        );
+
+        let shader = Shader::new(state, "./res/shaders/prefix_sum_shader.wgsl", "scan", Visibility::COMPUTE);
+        let mut prefix_sum = prefix_sum::PrefixSum::new(state, shader, &data, count);
+        prefix_sum.execute();
+
+        let bytes = state.read_buffer(&data, count as u64 * 4).unwrap();
+        let sums: &[u32] = bytemuck::cast_slice(&bytes);
+        let mut expected = 0u32;
+        for (index, (sum, value)) in sums.iter().zip(values).enumerate() {
+            assert_eq!(*sum, expected, "element {} of {}", index, count);
+            expected = expected.wrapping_add(value);
+        }
+    }
+}
+
+const IDENTITY_SHADER: &str = "
+@group(0) @binding(0) var<storage, read> input: array<u32>;
+@group(0) @binding(1) var<storage, read_write> output: array<u32>;
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= arrayLength(&input)) {
+        return;
+    }
+
+    output[id.x] = input[id.x];
+}
+";
+
+fn read_buffer_identity(state: &State) {
+    let data = (0..7u32).map(|i| i * 0x01010101).collect::<Vec<u32>>();
+    let input = state.create_buffer_init(&data, wgpu::BufferUsages::STORAGE, Access::Read);
+    let output = state.create_buffer(
+        std::mem::size_of_val(data.as_slice()) as u64, 
+        wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC, 
+        Access::Both
+    );
+
+    let mut shader = Shader::from_source(state, IDENTITY_SHADER.to_string(), "main", Visibility::COMPUTE);
+    shader.add_entry(Box::new(input.get_binding(None)));
+    shader.add_entry(Box::new(output.get_binding(None)));
+    ComputePipeline::new_1d(state, shader, data.len() as u32, None).execute();
+
+    assert_eq!(state.read_buffer_as::<u32>(&output, data.len()).unwrap(), data);
+
+    // unaligned size is rounded up for the copy and truncated back
+    let bytes = state.read_buffer(&output, 27).unwrap();
+    assert_eq!(bytes.as_slice(), &bytemuck::cast_slice::<u32, u8>(&data)[..27]);
+}
+
+fn render_pipeline_resize(state: &State) {
+    let vertex = Shader::new(state, SCREEN_SHADER, "vert_main", Visibility::VERTEX);
+    let fragment = vertex.new_from("frag_main", Visibility::FRAGMENT);
+    let mut pipeline = RenderPipeline::new(state, vertex, fragment);
+
+    pipeline.resize(Size::new(512, 512)).unwrap();
+    assert_eq!(pipeline.get_size().into_tuple(), (512, 512));
+
+    // the copy fails validation if the texture is smaller than the new size
+    let texture = pipeline.get_texture(Access::Read, false);
+    let data = state.read_texture(&texture, wgpu::Origin3d::ZERO, Size::new(512, 512)).unwrap();
+    assert_eq!(data.len(), 512 * 512 * 4);
+}
+
+/// Run under a leak checker (e.g. valgrind or -Zsanitizer=address) to catch double frees
+fn texture_swap_stress(state: &State) {
+    let size = Size::new(16, 16);
+    let usage = wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_SRC;
+    let mut texture = state.create_texture(size, usage, Access::Read, false);
+    let first = texture.get_view(None);
+
+    for _ in 0..1000 {
+        texture.swap_texture(state.create_raw_texture(size, usage));
+    }
+
+    // both the swapped in texture and the handle to the first one stay valid
+    assert_eq!(state.read_texture(&texture, wgpu::Origin3d::ZERO, size).unwrap().len(), 16 * 16 * 4);
+    assert_eq!(state.read_texture(&first, wgpu::Origin3d::ZERO, size).unwrap().len(), 16 * 16 * 4);
+}
+
+fn mass_conservation(state: &State) {
+    let shader = Shader::new(state, FLUID_SHADER, "main", Visibility::COMPUTE);
+    let mut closed = Fluid::new(state, shader, Size::new(10, 10));
+    let initial = closed.total_mass();
+    closed.run(50);
+
+    let balance = closed.mass_balance();
+    assert_eq!(closed.total_mass(), initial);
+    assert_eq!(balance.total, balance.initial);
+    assert!(!balance.leaking());
+
+    // moving further than two domain widths per step, a single wrap leaves the particle outside
+    let shader = Shader::new(state, FLUID_SHADER, "main", Visibility::COMPUTE);
+    let mut leaky = Fluid::from_particles(state, shader, vec![Particle::new(10f32, 10f32)], 1, 1f32);
+    leaky.set_boundaries([BoundaryKind::Periodic; 4]).unwrap();
+    leaky.apply_impulse([10f32, 10f32], 1f32, [1000f32, 0f32], false);
+    leaky.update();
+
+    let balance = leaky.mass_balance();
+    assert!(balance.imbalance() != 0f32);
+    assert!(balance.leaking());
+}
+
+fn texture_format_layout(state: &State) {
+    let texture = state.create_texture_with_format(
+        Size::new(8, 8), 
+        wgpu::TextureFormat::R32Float, 
+        wgpu::TextureUsages::STORAGE_BINDING, 
+        Access::Write, 
+        true
+    );
+
+    assert_eq!(texture.get_format(), wgpu::TextureFormat::R32Float);
+    assert!(matches!(
+        texture.get_layout(0, Visibility::COMPUTE).ty,
+        wgpu::BindingType::StorageTexture { format: wgpu::TextureFormat::R32Float, .. }
+    ));
+}
+
+const TWO_GROUP_SHADER: &str = "
+@group(0) @binding(0) var<storage, read> input: array<u32>;
+@group(1) @binding(0) var<storage, read_write> output: array<u32>;
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= arrayLength(&input)) {
+        return;
+    }
+
+    output[id.x] = input[id.x] * 2u;
+}
+";
+
+fn two_group_dispatch(state: &State) {
+    let data = (0..100u32).collect::<Vec<u32>>();
+    let input = state.create_buffer_init(&data, wgpu::BufferUsages::STORAGE, Access::Read);
+    let output = state.create_buffer(
+        std::mem::size_of_val(data.as_slice()) as u64, 
+        wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC, 
+        Access::Both
+    );
+
+    let mut shader = Shader::from_source(state, TWO_GROUP_SHADER.to_string(), "main", Visibility::COMPUTE);
+    shader.add_entry(Box::new(input.get_binding(None)));
+    assert_eq!(shader.add_group(vec![Box::new(output.get_binding(None))], Visibility::COMPUTE), 1);
+    ComputePipeline::new_1d(state, shader, data.len() as u32, None).execute();
+
+    let doubled = data.iter().map(|value| value * 2).collect::<Vec<u32>>();
+    assert_eq!(state.read_buffer_as::<u32>(&output, data.len()).unwrap(), doubled);
+}
+
+fn group_without_first(state: &State) {
+    let data = (0..100u32).collect::<Vec<u32>>();
+    let output = state.create_buffer_init(
+        &data, 
+        wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC, 
+        Access::Both
+    );
+
+    // nothing at group 0, the group still has to be bound (empty) at its index
+    let source = TWO_GROUP_SHADER
+        .replace("@group(0) @binding(0) var<storage, read> input: array<u32>;", "")
+        .replace("arrayLength(&input)", "arrayLength(&output)")
+        .replace("input[id.x]", "output[id.x]");
+    let mut shader = Shader::from_source(state, source, "main", Visibility::COMPUTE);
+    assert_eq!(shader.add_group(vec![Box::new(output.get_binding(None))], Visibility::COMPUTE), 1);
+    ComputePipeline::new_1d(state, shader, data.len() as u32, None).execute();
+
+    let doubled = data.iter().map(|value| value * 2).collect::<Vec<u32>>();
+    assert_eq!(state.read_buffer_as::<u32>(&output, data.len()).unwrap(), doubled);
+}
+
+const PUSH_CONSTANTS_SHADER: &str = "
+struct Params {
+    values: vec4<f32>,
+}
+
+var<push_constant> params: Params;
+@group(0) @binding(0) var<storage, read_write> output: vec4<f32>;
+
+@compute @workgroup_size(1)
+fn main() {
+    output = params.values;
+}
+";
+
+fn push_constants(state: &State) {
+    let output = state.create_buffer(16, wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC, Access::Both);
+    let mut shader = Shader::from_source(state, PUSH_CONSTANTS_SHADER.to_string(), "main", Visibility::COMPUTE);
+    shader.add_entry(Box::new(output.get_binding(None)));
+    let pipeline = ComputePipeline::try_new(state, shader, Size::new(1, 1), Some(Size::new(1, 1)));
+
+    if !state.device.features().contains(wgpu::Features::PUSH_CONSTANTS) {
+        assert!(pipeline.is_err());
+        eprintln!("adapter doesn't support push constants, skipping the dispatch");
+        return;
+    }
+
+    // the 16 bytes used by the shader are reserved on creation, 
+    // misuse is rejected instead of panicking in wgpu
+    let mut pipeline = pipeline.unwrap();
+    assert!(pipeline.set_push_constants(6).is_err());
+    assert!(pipeline.set_push_constants(8).is_err());
+    assert!(pipeline.execute_with(&[0u8; 20]).is_err());
+    assert!(pipeline.execute_with(&[0u8; 3]).is_err());
+
+    let values = [1f32, 2f32, 3f32, 4f32];
+    pipeline.execute_with(bytemuck::cast_slice(&values)).unwrap();
+    assert_eq!(state.read_buffer_as::<f32>(&output, 4).unwrap(), values);
+}
+
+fn particles_round_trip(state: &State) {
+    let particles = (0..10)
+        .map(|i| Particle::new(i as f32, 2f32 * i as f32))
+        .collect::<Vec<Particle>>();
+
+    let buffer = upload_particles(state, &particles);
+    let downloaded = download_particles(state, &buffer, particles.len()).unwrap();
+
+    for (particle, downloaded) in particles.iter().zip(downloaded.iter()) {
+        assert_eq!(particle.get_position(), downloaded.get_position());
+        assert_eq!(particle.get_velocity(), downloaded.get_velocity());
+        assert_eq!(particle.get_density(), downloaded.get_density());
+    }
+    assert_eq!(particles, downloaded);
+}
+
+const FILL_SHADER: &str = "
+@group(0) @binding(0) var output: texture_storage_2d<rgba8unorm, write>;
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let coords = vec2<i32>(id.xy);
+    if (any(coords >= textureDimensions(output))) {
+        return;
+    }
+
+    textureStore(output, coords, vec4<f32>(1f, 0f, 0f, 1f));
+}
+";
+
+fn texture_bind_group_submit(state: &State) {
+    let size = Size::new(16, 16);
+    let texture = state.create_texture(
+        size, 
+        wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC, 
+        Access::Write, 
+        true
+    );
+
+    // the bind group borrows the cached view of the texture
+    let mut shader = Shader::from_source(state, FILL_SHADER.to_string(), "main", Visibility::COMPUTE);
+    shader.add_entry(Box::new(texture.get_view(None)));
+    ComputePipeline::new(state, shader, size, None).execute();
+
+    let data = state.read_texture(&texture, wgpu::Origin3d::ZERO, size).unwrap();
+    assert!(data.chunks_exact(4).all(|pixel| pixel == [255, 0, 0, 255]));
+}
+
+const SAMPLE_SHADER: &str = "
+@group(0) @binding(0) var input: texture_2d<f32>;
+@group(0) @binding(1) var input_sampler: sampler;
+
+@fragment
+fn main(@location(0) uv: vec2<f32>) -> @location(0) vec4<f32> {
+    return textureSample(input, input_sampler, uv);
+}
+";
+
+fn texture_sample_type(state: &State) {
+    let size = Size::new(8, 8);
+    let ids = state
+        .create_texture_with_format(size, wgpu::TextureFormat::R32Uint, wgpu::TextureUsages::TEXTURE_BINDING, Access::Read, false)
+        .with_sample_type(wgpu::TextureSampleType::Uint);
+
+    assert!(matches!(
+        ids.get_layout(0, Visibility::FRAGMENT).ty,
+        wgpu::BindingType::Texture { sample_type: wgpu::TextureSampleType::Uint, .. }
+    ));
+
+    // non filterable texture read by filtering sampler is rejected before the pipeline is built
+    let density = state
+        .create_texture_with_format(size, wgpu::TextureFormat::R32Float, wgpu::TextureUsages::TEXTURE_BINDING, Access::Read, false)
+        .with_sample_type(wgpu::TextureSampleType::Float { filterable: false });
+    let mut shader = Shader::from_source(state, SAMPLE_SHADER.to_string(), "main", Visibility::FRAGMENT);
+    shader.add_entry(Box::new(density));
+    shader.create_sampler();
+
+    assert!(shader.validate_bindings().is_err());
+}
+
+#[test]
+#[ignore = "needs a gpu and a display"]
+fn gpu() {
+    let (_event_loop, window) = init_window();
+    // left out with a warning if the adapter lacks it
+    let state = pollster::block_on(State::new_with_features(&window, wgpu::Features::PUSH_CONSTANTS));
+
+    isolated_particle_pressure(&state);
+    isolated_particle_normal(&state);
+    leapfrog_energy_drift(&state);
+    neighbors_grow_with_smoothing_ratio(&state);
+    resize_texture_compute_pipeline(&state);
+    dropped_blob_falls(&state);
+    prefix_sum_matches_cpu(&state);
+    render_pipeline_resize(&state);
+    texture_swap_stress(&state);
+    read_buffer_identity(&state);
+    mass_conservation(&state);
+    texture_format_layout(&state);
+    two_group_dispatch(&state);
+    group_without_first(&state);
+    push_constants(&state);
+    particles_round_trip(&state);
+    texture_bind_group_submit(&state);
+    texture_sample_type(&state);
+}